@@ -0,0 +1,152 @@
+//! Live data shared between the Sensor & Frozen tasks
+//!
+//! MQTT isn't on the bus, both tasks already publish their own topics
+
+use tokio::sync::{broadcast, watch};
+
+use crate::{
+    common::packet::BedSide,
+    frozen::packet::{FrozenTarget, TemperatureUpdate},
+    sensor::{packet::TemperatureData, presence::PresenceState},
+};
+
+/// events a slow subscriber can fall behind by before missing some
+const CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SensorEvent {
+    /// only sent on changes
+    Presence(PresenceState),
+    BedTemperature(TemperatureData),
+    /// only sent on changes
+    Alarm {
+        side: BedSide,
+        running: bool,
+    },
+}
+
+/// only kept in the `Snapshot`, nothing needs every Frozen event (yet)
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrozenEvent {
+    WaterTemperature(TemperatureUpdate),
+    Target { side: BedSide, target: FrozenTarget },
+    Priming(bool),
+}
+
+/// Latest value of every event
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// `None` until presence is calibrated
+    pub presence: Option<PresenceState>,
+    pub bed_temp: Option<TemperatureData>,
+    pub alarm_left_running: bool,
+    pub alarm_right_running: bool,
+    pub water_temp: Option<TemperatureUpdate>,
+    pub left_target: Option<FrozenTarget>,
+    pub right_target: Option<FrozenTarget>,
+    pub is_priming: bool,
+}
+
+impl Snapshot {
+    /// returns if the event should be broadcast
+    fn apply_sensor(&mut self, event: &SensorEvent) -> bool {
+        match event {
+            SensorEvent::Presence(presence) => replace(&mut self.presence, Some(presence.clone())),
+            SensorEvent::BedTemperature(data) => {
+                self.bed_temp = Some(data.clone());
+                true
+            }
+            SensorEvent::Alarm { side, running } => match side {
+                BedSide::Left => replace(&mut self.alarm_left_running, *running),
+                BedSide::Right => replace(&mut self.alarm_right_running, *running),
+            },
+        }
+    }
+
+    /// returns if the snapshot changed
+    fn apply_frozen(&mut self, event: &FrozenEvent) -> bool {
+        match event {
+            FrozenEvent::WaterTemperature(u) => {
+                self.water_temp = Some(u.clone());
+                true
+            }
+            FrozenEvent::Target { side, target } => match side {
+                BedSide::Left => replace(&mut self.left_target, Some(target.clone())),
+                BedSide::Right => replace(&mut self.right_target, Some(target.clone())),
+            },
+            FrozenEvent::Priming(priming) => replace(&mut self.is_priming, *priming),
+        }
+    }
+}
+
+/// returns if `value` changed
+fn replace<T: PartialEq>(field: &mut T, value: T) -> bool {
+    let changed = *field != value;
+    *field = value;
+    changed
+}
+
+/// Sensor events for tasks that need every reading, plus a snapshot of
+/// the latest values for tasks that only care about the current state
+#[derive(Debug, Clone)]
+pub struct Bus {
+    sensor_tx: broadcast::Sender<SensorEvent>,
+    snapshot_tx: watch::Sender<Snapshot>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self {
+            sensor_tx: broadcast::channel(CAPACITY).0,
+            snapshot_tx: watch::channel(Snapshot::default()).0,
+        }
+    }
+}
+
+impl Bus {
+    pub fn send_sensor(&self, event: SensorEvent) {
+        if self
+            .snapshot_tx
+            .send_if_modified(|s| s.apply_sensor(&event))
+        {
+            // fine if nobody is subscribed
+            let _ = self.sensor_tx.send(event);
+        }
+    }
+
+    /// only updates the snapshot
+    pub fn send_frozen(&self, event: FrozenEvent) {
+        self.snapshot_tx
+            .send_if_modified(|s| s.apply_frozen(&event));
+    }
+
+    pub fn subscribe_sensor(&self) -> broadcast::Receiver<SensorEvent> {
+        self.sensor_tx.subscribe()
+    }
+
+    pub fn snapshot(&self) -> watch::Receiver<Snapshot> {
+        self.snapshot_tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_changes() {
+        let bus = Bus::default();
+        let mut rx = bus.subscribe_sensor();
+        let alarm = SensorEvent::Alarm {
+            side: BedSide::Right,
+            running: true,
+        };
+
+        bus.send_sensor(alarm.clone());
+        bus.send_sensor(alarm.clone());
+        assert_eq!(rx.try_recv(), Ok(alarm));
+        assert!(rx.try_recv().is_err());
+        assert!(bus.snapshot().borrow().alarm_right_running);
+        assert!(!bus.snapshot().borrow().alarm_left_running);
+    }
+}
@@ -1,17 +1,37 @@
 use super::{checksum, packet::Packet};
 use bytes::{Buf, BufMut, BytesMut};
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 use tokio_util::codec::{Decoder, Encoder};
 
 pub const START: u8 = 0x7E;
 
 pub struct PacketCodec<P: Packet> {
+    stats: CodecStats,
     _phantom: PhantomData<P>,
 }
 
+/// Decode failure counters, shared with whoever created the codec
+#[derive(Clone, Debug, Default)]
+pub struct CodecStats {
+    checksum_failures: Arc<AtomicU64>,
+}
+
+impl CodecStats {
+    pub fn checksum_failures(&self) -> u64 {
+        self.checksum_failures.load(Ordering::Relaxed)
+    }
+}
+
 impl<P: Packet> PacketCodec<P> {
-    pub fn new() -> Self {
+    pub fn new(stats: CodecStats) -> Self {
         Self {
+            stats,
             _phantom: PhantomData,
         }
     }
@@ -19,7 +39,7 @@ impl<P: Packet> PacketCodec<P> {
 
 impl<P: Packet> Default for PacketCodec<P> {
     fn default() -> Self {
-        Self::new()
+        Self::new(CodecStats::default())
     }
 }
 
@@ -66,6 +86,7 @@ impl<P: Packet> Decoder for PacketCodec<P> {
                     let expected_checksum = checksum::compute(payload);
 
                     if actual_checksum != expected_checksum {
+                        self.stats.checksum_failures.fetch_add(1, Ordering::Relaxed);
                         // bad checksum -> skip only start byte and try again
                         src.advance(1);
                         continue;
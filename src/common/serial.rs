@@ -1,4 +1,4 @@
-use super::codec::PacketCodec;
+use super::codec::{CodecStats, PacketCodec};
 use super::packet::Packet;
 use std::time::Duration;
 use strum_macros::Display;
@@ -47,7 +47,8 @@ pub fn create_port(port_path: &str, baud_rate: u32) -> Result<SerialStream, Seri
 pub fn create_framed_port<P: Packet>(
     port_path: &str,
     baud_rate: u32,
+    stats: CodecStats,
 ) -> Result<Framed<SerialStream, PacketCodec<P>>, SerialError> {
     let port = create_port(port_path, baud_rate)?;
-    Ok(Framed::new(port, PacketCodec::new()))
+    Ok(Framed::new(port, PacketCodec::new(stats)))
 }
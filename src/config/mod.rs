@@ -1,10 +1,18 @@
-use jiff::{civil::Time, tz::TimeZone};
+use std::{borrow::Cow, collections::BTreeMap, fmt, str::FromStr};
+
+use jiff::{
+    SignedDuration, Zoned,
+    civil::{Time, Weekday},
+    tz::TimeZone,
+};
 use ron::extensions::Extensions;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use strum_macros::{Display, EnumString};
 use thiserror::Error;
 use tokio::fs;
 
 use crate::common::packet::BedSide;
+use crate::frozen::packet::{MAX_TARGET_TEMP, MIN_TARGET_TEMP};
 use crate::led::{CurrentBand, LedPattern};
 use crate::sensor::command::AlarmPattern;
 
@@ -20,15 +28,28 @@ pub enum ConfigError {
     Io(#[from] std::io::Error),
     #[error("Failed to parse RON: {0}")]
     Ron(#[from] ron::error::SpannedError),
+    #[error("Invalid config: {0}")]
+    Invalid(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LEDConfig {
     pub idle: LedPattern,
     pub active: LedPattern,
+    /// while a fault has disabled temperature control
+    #[serde(default = "default_fault_led")]
+    pub fault: LedPattern,
     pub band: CurrentBand,
 }
 
+fn default_pause_without_water() -> bool {
+    true
+}
+
+fn default_fault_led() -> LedPattern {
+    LedPattern::FastPulse(255, 0, 0)
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MqttConfig {
     pub server: String,
@@ -46,6 +67,49 @@ pub struct AlarmConfig {
     pub offset: u32,
 }
 
+/// Allowed range of a side's temperatures, must be within the hard limits (13 to 43 degrees celcius)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureLimits {
+    /// degrees celcius
+    pub min: f32,
+    /// degrees celcius
+    pub max: f32,
+}
+
+impl Default for TemperatureLimits {
+    /// the hard limits
+    fn default() -> Self {
+        Self {
+            min: MIN_TARGET_TEMP as f32 / 100.0,
+            max: MAX_TARGET_TEMP as f32 / 100.0,
+        }
+    }
+}
+
+impl TemperatureLimits {
+    /// `temp` is degrees celcius, compared in centidegrees to ignore float error
+    pub fn contains(&self, temp: f32) -> bool {
+        let centi = |t: f32| (t * 100.0).round() as i32;
+        (centi(self.min)..=centi(self.max)).contains(&centi(temp))
+    }
+
+    /// all temperatures are centidegrees celcius
+    pub fn clamp(&self, temp: u16) -> u16 {
+        let min = (self.min * 100.0).round() as u16;
+        let max = (self.max * 100.0).round() as u16;
+        temp.clamp(min, max)
+    }
+
+    /// `MIN to MAX` in `unit`
+    pub fn display(self, unit: TemperatureUnit) -> String {
+        format!(
+            "{} to {}",
+            unit.convert_celsius(self.min),
+            unit.convert_celsius(self.max)
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PresenceConfig {
     pub baselines: [u16; 6],
@@ -71,8 +135,136 @@ fn timezone_ser<S: Serializer>(tz: &TimeZone, serializer: S) -> Result<S::Ok, S:
     serializer.serialize_str(tz.iana_name().unwrap())
 }
 
+fn keyframe_time_de<'de, D: Deserializer<'de>>(deserializer: D) -> Result<KeyframeTime, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+fn keyframe_time_ser<S: Serializer>(at: &KeyframeTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&at.to_string())
+}
+
+/// What a profile's `temperatures` are controlling
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, Display, EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum ControlMode {
+    /// water temperature
+    #[default]
+    Water,
+    /// bed surface temperature, from Sensor's bed thermistors
+    /// the water target is adjusted to reach it
+    Bed,
+}
+
+/// Unit of temperatures in the config file & on MQTT
+/// Internally everything is celcius
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, Display, EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum TemperatureUnit {
+    /// readings are published in centidegrees
+    #[default]
+    Celsius,
+    /// readings are published in degrees, to one decimal
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    /// degrees in this unit -> degrees celcius
+    pub fn to_celsius(self, temp: f32) -> f32 {
+        match self {
+            Self::Celsius => temp,
+            Self::Fahrenheit => (temp - 32.0) / 1.8,
+        }
+    }
+
+    /// degrees celcius -> degrees in this unit
+    pub fn convert_celsius(self, temp: f32) -> f32 {
+        match self {
+            Self::Celsius => temp,
+            // hides float error from converting back and forth
+            Self::Fahrenheit => (temp.mul_add(1.8, 32.0) * 100.0).round() / 100.0,
+        }
+    }
+
+    /// centidegrees celcius reading -> MQTT payload
+    pub fn format_reading(self, temp: u16) -> String {
+        match self {
+            Self::Celsius => temp.to_string(),
+            Self::Fahrenheit => format!("{:.1}", self.convert_reading(temp)),
+        }
+    }
+
+    /// centidegrees celcius reading -> centidegrees celcius or degrees fahrenheit to one decimal
+    pub fn convert_reading(self, temp: u16) -> f32 {
+        match self {
+            Self::Celsius => temp as f32,
+            Self::Fahrenheit => (self.convert_celsius(temp as f32 / 100.0) * 10.0).round() / 10.0,
+        }
+    }
+
+    /// centidegrees celcius -> ex. `21.5C` or `70.7F`, for messages
+    pub fn display_reading(self, temp: u16) -> String {
+        let symbol = match self {
+            Self::Celsius => 'C',
+            Self::Fahrenheit => 'F',
+        };
+        format!("{:.1}{symbol}", self.convert_celsius(temp as f32 / 100.0))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SideConfig {
+    /// degrees celcius
+    pub temperatures: Vec<f32>,
+    #[serde(default)]
+    pub control: ControlMode,
+    #[serde(deserialize_with = "time_de", serialize_with = "time_ser")]
+    pub sleep: Time,
+    #[serde(deserialize_with = "time_de", serialize_with = "time_ser")]
+    pub wake: Time,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alarm: Option<AlarmConfig>,
+    /// replaces `temperatures` when set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keyframes: Vec<Keyframe>,
+    /// drives towards the first temperature before the profile starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub precondition: Option<Precondition>,
+    /// starts the profile when the side is occupied, instead of at `sleep`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occupancy: Option<OccupancyConfig>,
+    /// overrides the above on specific nights, first match wins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<DaySchedule>,
+    /// name of the preset this was loaded from, cleared once modified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// every temperature must be within these, defaults to the hard limits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<TemperatureLimits>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Day {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// Schedule for nights starting on `days`
+/// IE `Friday` runs from Friday's `sleep` to Saturday's `wake` if it crosses midnight
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaySchedule {
+    pub days: Vec<Day>,
     /// degrees celcius
     pub temperatures: Vec<f32>,
     #[serde(deserialize_with = "time_de", serialize_with = "time_ser")]
@@ -81,6 +273,72 @@ pub struct SideConfig {
     pub wake: Time,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub alarm: Option<AlarmConfig>,
+    /// replaces `temperatures` when set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// How the temperature moves from one keyframe to the next
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, Display, EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum Curve {
+    /// holds the keyframe's temperature until the next one
+    Step,
+    #[default]
+    Linear,
+    /// eases in and out (smoothstep)
+    Smooth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Anchor {
+    Sleep,
+    Wake,
+}
+
+/// A time relative to `sleep` or `wake`, IE `sleep-30m`, `sleep+1h`, `wake-20m`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyframeTime {
+    pub anchor: Anchor,
+    pub offset: SignedDuration,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    #[serde(
+        deserialize_with = "keyframe_time_de",
+        serialize_with = "keyframe_time_ser"
+    )]
+    pub at: KeyframeTime,
+    /// degrees celcius
+    pub temp: f32,
+    /// towards the next keyframe
+    #[serde(default)]
+    pub curve: Curve,
+}
+
+/// Lead time before the profile starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Precondition {
+    /// minutes
+    Fixed(u32),
+    /// estimated from the water temperature & the learned heating/cooling rate
+    /// at most this many minutes
+    Learned(u32),
+}
+
+/// Presence-triggered profile, requires presence calibration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OccupancyConfig {
+    /// minutes before `sleep` that getting into bed starts the profile
+    pub before: u32,
+    /// minutes after `sleep` that getting into bed starts the profile
+    pub after: u32,
+    /// minutes empty before turning off (until occupied again)
+    pub off_after: u32,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -96,9 +354,18 @@ pub struct Config {
     pub away_mode: bool,
     #[serde(deserialize_with = "time_de", serialize_with = "time_ser")]
     pub prime: Time,
+    /// disable temperature control while the water tank is out
+    #[serde(default = "default_pause_without_water")]
+    pub pause_without_water: bool,
+    /// profile & preset temperatures in this file are in this unit
+    #[serde(default)]
+    pub unit: TemperatureUnit,
     pub led: LEDConfig,
     pub mqtt: MqttConfig,
     pub profile: SidesConfig,
+    /// named profiles that a side can be switched to
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub presets: BTreeMap<String, SideConfig>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub presence: Option<PresenceConfig>,
 }
@@ -107,16 +374,253 @@ impl Config {
     pub async fn load(path: &str) -> Result<Self, ConfigError> {
         let content = fs::read_to_string(path).await?;
         let opts = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
-        let config = opts.from_str(&content)?;
+        let mut config: Config = opts.from_str(&content)?;
+        let unit = config.unit;
+        config.map_temperatures(|t| unit.to_celsius(t));
+        config.validate()?;
         Ok(config)
     }
 
     pub async fn save(&self, path: &str) -> Result<(), ConfigError> {
-        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+        let mut config = self.clone();
+        config.map_temperatures(|t| self.unit.convert_celsius(t));
+        let content = ron::ser::to_string_pretty(&config, ron::ser::PrettyConfig::default())
             .map_err(|e| ConfigError::Io(std::io::Error::other(e)))?;
         fs::write(path, content).await?;
         Ok(())
     }
+
+    /// checks every profile & preset is within its limits
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let sides = match &self.profile {
+            SidesConfig::Solo(solo) => vec![("profile".to_string(), solo)],
+            SidesConfig::Couples { left, right } => {
+                vec![("left".to_string(), left), ("right".to_string(), right)]
+            }
+        };
+        let presets = self
+            .presets
+            .iter()
+            .map(|(name, preset)| (format!("preset `{name}`"), preset));
+
+        for (name, side) in sides.into_iter().chain(presets) {
+            side.validate(self.unit)
+                .map_err(|e| ConfigError::Invalid(format!("{name}: {e}")))?;
+        }
+        Ok(())
+    }
+
+    /// applies `f` to every profile & preset temperature
+    fn map_temperatures(&mut self, f: impl Fn(f32) -> f32 + Copy) {
+        match &mut self.profile {
+            SidesConfig::Solo(solo) => solo.map_temperatures(f),
+            SidesConfig::Couples { left, right } => {
+                left.map_temperatures(f);
+                right.map_temperatures(f);
+            }
+        }
+        for preset in self.presets.values_mut() {
+            preset.map_temperatures(f);
+        }
+    }
+}
+
+impl FromStr for KeyframeTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(['+', '-']).unwrap_or(s.len());
+        let (anchor, offset) = s.split_at(split);
+
+        let anchor = anchor
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid anchor `{anchor}`. Must be `sleep` or `wake`"))?;
+
+        let offset = match offset.split_at_checked(1) {
+            None => SignedDuration::ZERO,
+            Some((sign, dur)) => {
+                let dur: SignedDuration = dur
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("Invalid offset `{dur}`: {e}"))?;
+                if sign == "-" { -dur } else { dur }
+            }
+        };
+
+        Ok(Self { anchor, offset })
+    }
+}
+
+impl fmt::Display for KeyframeTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.offset.is_zero() {
+            write!(f, "{}", self.anchor)
+        } else if self.offset.is_negative() {
+            write!(f, "{}-{:#}", self.anchor, self.offset.abs())
+        } else {
+            write!(f, "{}+{:#}", self.anchor, self.offset)
+        }
+    }
+}
+
+impl From<Weekday> for Day {
+    fn from(weekday: Weekday) -> Self {
+        match weekday {
+            Weekday::Monday => Day::Monday,
+            Weekday::Tuesday => Day::Tuesday,
+            Weekday::Wednesday => Day::Wednesday,
+            Weekday::Thursday => Day::Thursday,
+            Weekday::Friday => Day::Friday,
+            Weekday::Saturday => Day::Saturday,
+            Weekday::Sunday => Day::Sunday,
+        }
+    }
+}
+
+impl SideConfig {
+    /// this config with the schedule for nights starting on `day` applied
+    pub fn for_night(&self, day: Day) -> Cow<'_, SideConfig> {
+        match self.schedules.iter().find(|s| s.days.contains(&day)) {
+            Some(schedule) => Cow::Owned(SideConfig {
+                temperatures: schedule.temperatures.clone(),
+                control: self.control,
+                sleep: schedule.sleep,
+                wake: schedule.wake,
+                alarm: schedule.alarm.clone(),
+                keyframes: schedule.keyframes.clone(),
+                precondition: self.precondition,
+                occupancy: self.occupancy,
+                schedules: vec![],
+                preset: self.preset.clone(),
+                limits: self.limits,
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// the schedule of last night if it's still running, otherwise tonight's
+    pub fn for_now(&self, now: &Zoned) -> Cow<'_, SideConfig> {
+        let last_night = self.for_night(now.weekday().previous().into());
+        if last_night.crosses_midnight() && now.time() <= last_night.wake {
+            last_night
+        } else {
+            self.for_night(now.weekday().into())
+        }
+    }
+
+    /// schedules waking up on `now`'s day
+    pub fn waking_today(&self, now: &Zoned) -> Vec<Cow<'_, SideConfig>> {
+        let last_night = self.for_night(now.weekday().previous().into());
+        let tonight = self.for_night(now.weekday().into());
+        let mut res = vec![];
+        if last_night.crosses_midnight() {
+            res.push(last_night);
+        }
+        if !tonight.crosses_midnight() {
+            res.push(tonight);
+        }
+        res
+    }
+
+    pub fn crosses_midnight(&self) -> bool {
+        self.wake < self.sleep
+    }
+
+    /// the schedule for nights starting on `day`, only applying to that day
+    /// it's split out of a shared schedule, or created from the defaults
+    pub fn day_schedule_mut(&mut self, day: Day) -> &mut DaySchedule {
+        let schedule = match self.schedules.iter().position(|s| s.days.contains(&day)) {
+            Some(i) if self.schedules[i].days == [day] => return &mut self.schedules[i],
+            Some(i) => {
+                self.schedules[i].days.retain(|d| d != &day);
+                self.schedules[i].clone()
+            }
+            None => DaySchedule {
+                days: vec![],
+                temperatures: self.temperatures.clone(),
+                sleep: self.sleep,
+                wake: self.wake,
+                alarm: self.alarm.clone(),
+                keyframes: self.keyframes.clone(),
+            },
+        };
+
+        self.schedules.push(DaySchedule {
+            days: vec![day],
+            ..schedule
+        });
+        self.schedules.last_mut().unwrap()
+    }
+
+    /// makes nights starting on `day` use the defaults
+    pub fn clear_day(&mut self, day: Day) {
+        for schedule in &mut self.schedules {
+            schedule.days.retain(|d| d != &day);
+        }
+        self.schedules.retain(|s| !s.days.is_empty());
+    }
+
+    pub fn limits(&self) -> TemperatureLimits {
+        self.limits.unwrap_or_default()
+    }
+
+    /// checks `limits` are within the hard limits & every temperature is within `limits`
+    /// errors are in `unit`
+    pub fn validate(&self, unit: TemperatureUnit) -> Result<(), String> {
+        let hard = TemperatureLimits::default();
+        let limits = self.limits();
+        if limits.min > limits.max || !hard.contains(limits.min) || !hard.contains(limits.max) {
+            return Err(format!(
+                "limits {} must be within {}",
+                limits.display(unit),
+                hard.display(unit)
+            ));
+        }
+
+        let keyframes = self.keyframes.iter().map(|k| &k.temp);
+        let schedules = self.schedules.iter().flat_map(|s| {
+            s.temperatures
+                .iter()
+                .chain(s.keyframes.iter().map(|k| &k.temp))
+        });
+        match self
+            .temperatures
+            .iter()
+            .chain(keyframes)
+            .chain(schedules)
+            .find(|t| !limits.contains(**t))
+        {
+            Some(temp) => Err(format!(
+                "temperature {} is outside of the limits ({})",
+                unit.convert_celsius(*temp),
+                limits.display(unit)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// applies `f` to every temperature, including schedules & limits
+    pub fn map_temperatures(&mut self, f: impl Fn(f32) -> f32 + Copy) {
+        map_temperatures(&mut self.temperatures, &mut self.keyframes, f);
+        if let Some(limits) = &mut self.limits {
+            limits.min = f(limits.min);
+            limits.max = f(limits.max);
+        }
+        for schedule in &mut self.schedules {
+            map_temperatures(&mut schedule.temperatures, &mut schedule.keyframes, f);
+        }
+    }
+}
+
+fn map_temperatures(temperatures: &mut [f32], keyframes: &mut [Keyframe], f: impl Fn(f32) -> f32) {
+    for temp in temperatures {
+        *temp = f(*temp);
+    }
+    for keyframe in keyframes {
+        keyframe.temp = f(keyframe.temp);
+    }
 }
 
 impl SidesConfig {
@@ -5,7 +5,10 @@ use crate::{
     mqtt::publish_guaranteed_wait,
 };
 
-use super::{AlarmConfig, CONFIG_FILE, Config, SidesConfig};
+use super::{
+    AlarmConfig, CONFIG_FILE, Config, Curve, Day, Keyframe, OccupancyConfig, Precondition,
+    SidesConfig, TemperatureLimits, TemperatureUnit,
+};
 use jiff::civil::Time;
 use rumqttc::AsyncClient;
 use tokio::sync::watch;
@@ -13,22 +16,59 @@ use tokio::sync::watch;
 const TOPIC_TIMEZONE: &str = "opensleep/state/config/timezone";
 const TOPIC_AWAY_MODE: &str = "opensleep/state/config/away_mode";
 const TOPIC_PRIME: &str = "opensleep/state/config/prime";
+const TOPIC_UNIT: &str = "opensleep/state/config/unit";
+const TOPIC_PAUSE_WITHOUT_WATER: &str = "opensleep/state/config/pause_without_water";
 
 const TOPIC_LED_IDLE: &str = "opensleep/state/config/led/idle";
 const TOPIC_LED_ACTIVE: &str = "opensleep/state/config/led/active";
+const TOPIC_LED_FAULT: &str = "opensleep/state/config/led/fault";
 const TOPIC_LED_BAND: &str = "opensleep/state/config/led/band";
 
 const TOPIC_PROFILE_TYPE: &str = "opensleep/state/config/profile/type";
+const TOPIC_PRESETS: &str = "opensleep/state/config/presets";
+
+/// retained state topics for one side's profile
+struct ProfileTopics {
+    sleep: &'static str,
+    wake: &'static str,
+    temperatures: &'static str,
+    keyframes: &'static str,
+    alarm: &'static str,
+    precondition: &'static str,
+    occupancy: &'static str,
+    control: &'static str,
+    schedules: &'static str,
+    preset: &'static str,
+    limits: &'static str,
+}
 
-const TOPIC_PROFILE_LEFT_SLEEP: &str = "opensleep/state/config/profile/left/sleep";
-const TOPIC_PROFILE_LEFT_WAKE: &str = "opensleep/state/config/profile/left/wake";
-const TOPIC_PROFILE_LEFT_TEMPERATURES: &str = "opensleep/state/config/profile/left/temperatures";
-const TOPIC_PROFILE_LEFT_ALARM: &str = "opensleep/state/config/profile/left/alarm";
+const TOPICS_PROFILE_LEFT: ProfileTopics = ProfileTopics {
+    sleep: "opensleep/state/config/profile/left/sleep",
+    wake: "opensleep/state/config/profile/left/wake",
+    temperatures: "opensleep/state/config/profile/left/temperatures",
+    keyframes: "opensleep/state/config/profile/left/keyframes",
+    alarm: "opensleep/state/config/profile/left/alarm",
+    precondition: "opensleep/state/config/profile/left/precondition",
+    occupancy: "opensleep/state/config/profile/left/occupancy",
+    control: "opensleep/state/config/profile/left/control",
+    schedules: "opensleep/state/config/profile/left/schedules",
+    preset: "opensleep/state/config/profile/left/preset",
+    limits: "opensleep/state/config/profile/left/limits",
+};
 
-const TOPIC_PROFILE_RIGHT_SLEEP: &str = "opensleep/state/config/profile/right/sleep";
-const TOPIC_PROFILE_RIGHT_WAKE: &str = "opensleep/state/config/profile/right/wake";
-const TOPIC_PROFILE_RIGHT_TEMPERATURES: &str = "opensleep/state/config/profile/right/temperatures";
-const TOPIC_PROFILE_RIGHT_ALARM: &str = "opensleep/state/config/profile/right/alarm";
+const TOPICS_PROFILE_RIGHT: ProfileTopics = ProfileTopics {
+    sleep: "opensleep/state/config/profile/right/sleep",
+    wake: "opensleep/state/config/profile/right/wake",
+    temperatures: "opensleep/state/config/profile/right/temperatures",
+    keyframes: "opensleep/state/config/profile/right/keyframes",
+    alarm: "opensleep/state/config/profile/right/alarm",
+    precondition: "opensleep/state/config/profile/right/precondition",
+    occupancy: "opensleep/state/config/profile/right/occupancy",
+    control: "opensleep/state/config/profile/right/control",
+    schedules: "opensleep/state/config/profile/right/schedules",
+    preset: "opensleep/state/config/profile/right/preset",
+    limits: "opensleep/state/config/profile/right/limits",
+};
 
 const TOPIC_PRESENCE_BASELINES: &str = "opensleep/state/config/presence/baselines";
 const TOPIC_PRESENCE_THRESHOLD: &str = "opensleep/state/config/presence/threshold";
@@ -37,6 +77,7 @@ const TOPIC_PRESENCE_DEBOUNCE_COUNT: &str = "opensleep/state/config/presence/deb
 pub const TOPIC_SET_AWAY_MODE: &str = "opensleep/actions/set_away_mode";
 pub const TOPIC_SET_PRIME: &str = "opensleep/actions/set_prime";
 pub const TOPIC_SET_PROFILE: &str = "opensleep/actions/set_profile";
+pub const TOPIC_SET_PRESET: &str = "opensleep/actions/set_preset";
 pub const TOPIC_SET_PRESENCE: &str = "opensleep/actions/set_presence_config";
 
 impl PresenceConfig {
@@ -71,26 +112,18 @@ impl PresenceConfig {
 }
 
 impl SidesConfig {
-    async fn publish(&self, client: &mut AsyncClient) {
+    async fn publish(&self, client: &mut AsyncClient, unit: TemperatureUnit) {
         match &self {
             SidesConfig::Solo(solo) => {
                 publish_guaranteed_wait(client, TOPIC_PROFILE_TYPE, true, "solo").await;
-                publish_left_profile(client, solo).await;
+                publish_left_profile(client, solo, unit).await;
             }
             SidesConfig::Couples { left, right } => {
                 publish_guaranteed_wait(client, TOPIC_PROFILE_TYPE, true, "couples").await;
 
-                publish_left_profile(client, left).await;
+                publish_left_profile(client, left, unit).await;
 
-                publish_profile(
-                    client,
-                    right,
-                    TOPIC_PROFILE_RIGHT_SLEEP,
-                    TOPIC_PROFILE_RIGHT_WAKE,
-                    TOPIC_PROFILE_RIGHT_TEMPERATURES,
-                    TOPIC_PROFILE_RIGHT_ALARM,
-                )
-                .await;
+                publish_profile(client, right, &TOPICS_PROFILE_RIGHT, unit).await;
             }
         }
     }
@@ -111,6 +144,11 @@ impl Config {
 
         publish_prime(client, self.prime).await;
 
+        publish_guaranteed_wait(client, TOPIC_UNIT, true, self.unit.to_string()).await;
+
+        let pause = self.pause_without_water.to_string();
+        publish_guaranteed_wait(client, TOPIC_PAUSE_WITHOUT_WATER, true, pause).await;
+
         // led
         publish_guaranteed_wait(client, TOPIC_LED_IDLE, true, format!("{:?}", self.led.idle)).await;
         publish_guaranteed_wait(
@@ -120,6 +158,13 @@ impl Config {
             format!("{:?}", self.led.active),
         )
         .await;
+        publish_guaranteed_wait(
+            client,
+            TOPIC_LED_FAULT,
+            true,
+            format!("{:?}", self.led.fault),
+        )
+        .await;
         publish_guaranteed_wait(client, TOPIC_LED_BAND, true, self.led.band.to_string()).await;
 
         // presence
@@ -127,7 +172,8 @@ impl Config {
             presence.publish(client).await;
         }
 
-        self.profile.publish(client).await;
+        self.profile.publish(client, self.unit).await;
+        publish_presets(client, self).await;
 
         log::debug!("Published config");
     }
@@ -141,36 +187,76 @@ async fn publish_away_mode(client: &mut AsyncClient, mode: bool) {
     publish_guaranteed_wait(client, TOPIC_AWAY_MODE, true, mode.to_string()).await;
 }
 
-async fn publish_left_profile(client: &mut AsyncClient, side: &SideConfig) {
-    publish_profile(
-        client,
-        side,
-        TOPIC_PROFILE_LEFT_SLEEP,
-        TOPIC_PROFILE_LEFT_WAKE,
-        TOPIC_PROFILE_LEFT_TEMPERATURES,
-        TOPIC_PROFILE_LEFT_ALARM,
-    )
-    .await;
+async fn publish_left_profile(client: &mut AsyncClient, side: &SideConfig, unit: TemperatureUnit) {
+    publish_profile(client, side, &TOPICS_PROFILE_LEFT, unit).await;
 }
 
 async fn publish_profile(
     client: &mut AsyncClient,
     side: &SideConfig,
-    topic_sleep: &'static str,
-    topic_wake: &'static str,
-    topic_temps: &'static str,
-    topic_alarm: &'static str,
+    topics: &ProfileTopics,
+    unit: TemperatureUnit,
 ) {
-    publish_guaranteed_wait(client, topic_sleep, true, side.sleep.to_string()).await;
-    publish_guaranteed_wait(client, topic_wake, true, side.wake.to_string()).await;
+    let mut side = side.clone();
+    side.map_temperatures(|t| unit.convert_celsius(t));
+
+    publish_guaranteed_wait(client, topics.sleep, true, side.sleep.to_string()).await;
+    publish_guaranteed_wait(client, topics.wake, true, side.wake.to_string()).await;
     publish_guaranteed_wait(
         client,
-        topic_temps,
+        topics.temperatures,
         true,
         temps_to_string(&side.temperatures),
     )
     .await;
-    publish_guaranteed_wait(client, topic_alarm, true, alarm_to_string(&side.alarm)).await;
+    publish_guaranteed_wait(
+        client,
+        topics.keyframes,
+        true,
+        keyframes_to_string(&side.keyframes),
+    )
+    .await;
+    publish_guaranteed_wait(client, topics.alarm, true, alarm_to_string(&side.alarm)).await;
+    publish_guaranteed_wait(
+        client,
+        topics.precondition,
+        true,
+        precondition_to_string(&side.precondition),
+    )
+    .await;
+    publish_guaranteed_wait(
+        client,
+        topics.occupancy,
+        true,
+        occupancy_to_string(&side.occupancy),
+    )
+    .await;
+    publish_guaranteed_wait(client, topics.control, true, side.control.to_string()).await;
+    publish_guaranteed_wait(
+        client,
+        topics.schedules,
+        true,
+        serde_json::to_string(&side.schedules).unwrap_or_default(),
+    )
+    .await;
+    publish_guaranteed_wait(
+        client,
+        topics.preset,
+        true,
+        side.preset.as_deref().unwrap_or("custom"),
+    )
+    .await;
+    publish_guaranteed_wait(client, topics.limits, true, limits_to_string(&side.limits)).await;
+}
+
+async fn publish_presets(client: &mut AsyncClient, cfg: &Config) {
+    publish_guaranteed_wait(
+        client,
+        TOPIC_PRESETS,
+        true,
+        cfg.presets.keys().cloned().collect::<Vec<_>>().join(","),
+    )
+    .await;
 }
 
 pub async fn handle_action(
@@ -195,61 +281,96 @@ pub async fn handle_action(
         }
 
         TOPIC_SET_PROFILE => {
-            // TARGET.FIELD=VALUE
-            let (target, rhs) = payload
+            // TARGET.FIELD=VALUE, TARGET.DAY.FIELD=VALUE, or TARGET.DAY=default
+            let (lhs, value) = payload
                 .trim()
-                .split_once('.')
+                .split_once('=')
                 .ok_or("Invalid input. Requires `TARGET.FIELD=VALUE`")?;
 
-            let (field, value) = rhs
+            let (target, field) = lhs
                 .trim()
-                .split_once('=')
+                .split_once('.')
                 .ok_or("Invalid input. Requires `TARGET.FIELD=VALUE`")?;
 
-            if ["left", "right"].contains(&target) && cfg.profile.is_solo() {
-                return Err(
-                    "Cannot modify profile in `couples` mode (currently in `solo` mode)".into(),
-                );
-            }
+            let unit = cfg.unit;
+            let profile = get_target_mut(&mut cfg.profile, target)?;
+            // no longer matches its preset
+            profile.preset = None;
 
-            let profile = match target {
-                "left" => cfg.profile.unwrap_left_mut(),
-                "right" => cfg.profile.unwrap_right_mut(),
-                "both" => {
-                    if cfg.profile.is_couples() {
+            match (field.split_once('.'), field.parse::<Day>()) {
+                (Some((day, day_field)), _) => {
+                    set_day_field(profile, day.parse()?, day_field, value, unit)?;
+                }
+                (None, Ok(day)) => {
+                    if value.trim() != "default" {
+                        return Err("Invalid input. Requires `TARGET.DAY=default`".into());
+                    }
+                    profile.clear_day(day);
+                }
+                (None, Err(_)) => match field {
+                    "sleep" => {
+                        profile.sleep = value.parse()?;
+                    }
+                    "wake" => {
+                        profile.wake = value.parse()?;
+                    }
+                    "temperatures" => {
+                        profile.temperatures = parse_temperatures(value, unit)?;
+                    }
+                    "keyframes" => {
+                        profile.keyframes = parse_keyframes(value, unit)?;
+                    }
+                    "alarm" => {
+                        profile.alarm = parse_alarm(value)?;
+                    }
+                    "precondition" => {
+                        profile.precondition = parse_precondition(value)?;
+                    }
+                    "occupancy" => {
+                        profile.occupancy = parse_occupancy(value)?;
+                    }
+                    "control" => {
+                        profile.control = value.trim().parse()?;
+                    }
+                    "limits" => {
+                        profile.limits = parse_limits(value, unit)?;
+                    }
+                    _ => {
                         return Err(
-                            "Cannot modify profile in `solo` mode (currently in `couples` mode)"
+                            "Invalid FIELD. Must be `sleep`, `wake`, `temperatures`, `keyframes`, `alarm`, `precondition`, `occupancy`, `control`, or `limits`"
                                 .into(),
                         );
                     }
-
-                    cfg.profile.unwrap_solo_mut()
-                }
-                _ => return Err("Invalid TARGET. Must be `left`, `right`, or `both`".into()),
-            };
-
-            match field {
-                "sleep" => {
-                    profile.sleep = value.parse()?;
-                }
-                "wake" => {
-                    profile.wake = value.parse()?;
-                }
-                "temperatures" => {
-                    profile.temperatures = parse_temperatures(value)?;
-                }
-                "alarm" => {
-                    profile.alarm = parse_alarm(value)?;
-                }
-                _ => {
-                    return Err(
-                        "Invalid FIELD. Must be `sleep`, `wake`, `temperatures`, or `alarm`".into(),
-                    );
-                }
+                },
             }
+            cfg.validate()?;
 
             log::info!("Updated profile ({target}::{field} -> {value})");
-            cfg.profile.publish(client).await;
+            cfg.profile.publish(client, cfg.unit).await;
+        }
+
+        TOPIC_SET_PRESET => {
+            // TARGET=NAME
+            let (target, name) = payload
+                .trim()
+                .split_once('=')
+                .ok_or("Invalid input. Requires `TARGET=NAME`")?;
+
+            let name = name.trim();
+            let preset = cfg.presets.get(name).ok_or_else(|| {
+                format!(
+                    "Unknown preset `{name}`. Must be one of: {}",
+                    cfg.presets.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            })?;
+
+            *get_target_mut(&mut cfg.profile, target)? = SideConfig {
+                preset: Some(name.to_string()),
+                ..preset.clone()
+            };
+
+            log::info!("Switched profile {target} to preset {name}");
+            cfg.profile.publish(client, cfg.unit).await;
         }
 
         TOPIC_SET_PRESENCE => {
@@ -298,11 +419,112 @@ pub async fn handle_action(
     Ok(())
 }
 
-fn parse_temperatures(value: &str) -> Result<Vec<f32>, String> {
+/// the side(s) `TARGET` refers to in `set_profile` & `set_preset`
+fn get_target_mut<'a>(
+    profile: &'a mut SidesConfig,
+    target: &str,
+) -> Result<&'a mut SideConfig, Box<dyn Error>> {
+    if ["left", "right"].contains(&target) && profile.is_solo() {
+        return Err("Cannot modify profile in `couples` mode (currently in `solo` mode)".into());
+    }
+
+    match target {
+        "left" => Ok(profile.unwrap_left_mut()),
+        "right" => Ok(profile.unwrap_right_mut()),
+        "both" => {
+            if profile.is_couples() {
+                return Err(
+                    "Cannot modify profile in `solo` mode (currently in `couples` mode)".into(),
+                );
+            }
+
+            Ok(profile.unwrap_solo_mut())
+        }
+        _ => Err("Invalid TARGET. Must be `left`, `right`, or `both`".into()),
+    }
+}
+
+fn set_day_field(
+    profile: &mut SideConfig,
+    day: Day,
+    field: &str,
+    value: &str,
+    unit: TemperatureUnit,
+) -> Result<(), Box<dyn Error>> {
+    // validate before splitting the day out
+    match field {
+        "sleep" => {
+            let sleep = value.parse()?;
+            profile.day_schedule_mut(day).sleep = sleep;
+        }
+        "wake" => {
+            let wake = value.parse()?;
+            profile.day_schedule_mut(day).wake = wake;
+        }
+        "temperatures" => {
+            let temperatures = parse_temperatures(value, unit)?;
+            profile.day_schedule_mut(day).temperatures = temperatures;
+        }
+        "keyframes" => {
+            let keyframes = parse_keyframes(value, unit)?;
+            profile.day_schedule_mut(day).keyframes = keyframes;
+        }
+        "alarm" => {
+            let alarm = parse_alarm(value)?;
+            profile.day_schedule_mut(day).alarm = alarm;
+        }
+        _ => {
+            return Err(
+                "Invalid FIELD. Must be `sleep`, `wake`, `temperatures`, `keyframes`, or `alarm`"
+                    .into(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// returns degrees celcius
+fn parse_temperatures(value: &str, unit: TemperatureUnit) -> Result<Vec<f32>, String> {
     value
         .trim()
         .split(',')
-        .map(|s| s.trim().parse::<f32>().map_err(|e| e.to_string()))
+        .map(|s| {
+            s.trim()
+                .parse::<f32>()
+                .map(|t| unit.to_celsius(t))
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// `AT:TEMP[:CURVE],..` or `none`, returns degrees celcius
+fn parse_keyframes(value: &str, unit: TemperatureUnit) -> Result<Vec<Keyframe>, String> {
+    let trimmed = value.trim();
+    if trimmed == "none" {
+        return Ok(vec![]);
+    }
+
+    trimmed
+        .split(',')
+        .map(|s| {
+            let mut parts = s.split(':');
+            let at = parts.next().unwrap_or_default().parse()?;
+            let temp = parts
+                .next()
+                .ok_or(format!("Missing temperature in keyframe `{s}`"))?
+                .trim()
+                .parse()
+                .map(|t| unit.to_celsius(t))
+                .map_err(|e| format!("Invalid temperature: {e}"))?;
+            let curve = match parts.next() {
+                Some(c) => c
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("Invalid curve: {e}"))?,
+                None => Curve::default(),
+            };
+            Ok(Keyframe { at, temp, curve })
+        })
         .collect()
 }
 
@@ -346,6 +568,75 @@ fn parse_alarm(value: &str) -> Result<Option<AlarmConfig>, String> {
     }))
 }
 
+/// `fixed,MINUTES`, `learned,MAX_MINUTES` or `disabled`
+fn parse_precondition(value: &str) -> Result<Option<Precondition>, String> {
+    let trimmed = value.trim();
+    if trimmed == "disabled" {
+        return Ok(None);
+    }
+
+    let (kind, mins) = trimmed
+        .split_once(',')
+        .ok_or("Expected `fixed,MINUTES`, `learned,MAX_MINUTES` or `disabled`")?;
+    let mins = mins
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid minutes: {e}"))?;
+
+    match kind.trim() {
+        "fixed" => Ok(Some(Precondition::Fixed(mins))),
+        "learned" => Ok(Some(Precondition::Learned(mins))),
+        _ => Err(format!(
+            "Invalid kind `{kind}`. Must be `fixed` or `learned`"
+        )),
+    }
+}
+
+/// `MIN,MAX` or `default`, returns degrees celcius
+fn parse_limits(value: &str, unit: TemperatureUnit) -> Result<Option<TemperatureLimits>, String> {
+    let trimmed = value.trim();
+    if trimmed == "default" {
+        return Ok(None);
+    }
+
+    let (min, max) = trimmed
+        .split_once(',')
+        .ok_or("Expected `MIN,MAX` or `default`")?;
+    let parse = |name: &str, s: &str| {
+        s.trim()
+            .parse()
+            .map(|t| unit.to_celsius(t))
+            .map_err(|e| format!("Invalid {name}: {e}"))
+    };
+    Ok(Some(TemperatureLimits {
+        min: parse("min", min)?,
+        max: parse("max", max)?,
+    }))
+}
+
+/// `BEFORE,AFTER,OFF_AFTER` (minutes) or `disabled`
+fn parse_occupancy(value: &str) -> Result<Option<OccupancyConfig>, String> {
+    let trimmed = value.trim();
+    if trimmed == "disabled" {
+        return Ok(None);
+    }
+
+    let parts: Vec<&str> = trimmed.split(',').collect();
+    if parts.len() != 3 {
+        return Err(format!(
+            "Expected 3 comma-separated values or 'disabled', got {}",
+            parts.len()
+        ));
+    }
+
+    let parse = |name: &str, s: &str| s.trim().parse().map_err(|e| format!("Invalid {name}: {e}"));
+    Ok(Some(OccupancyConfig {
+        before: parse("before", parts[0])?,
+        after: parse("after", parts[1])?,
+        off_after: parse("off_after", parts[2])?,
+    }))
+}
+
 fn parse_baselines(value: &str) -> Result<[u16; 6], String> {
     let values: Result<Vec<u16>, _> = value
         .trim()
@@ -376,7 +667,41 @@ fn alarm_to_string(alarm: &Option<AlarmConfig>) -> String {
     }
 }
 
-fn temps_to_string(temps: &Vec<f32>) -> String {
+fn precondition_to_string(precondition: &Option<Precondition>) -> String {
+    match precondition {
+        Some(Precondition::Fixed(mins)) => format!("fixed,{mins}"),
+        Some(Precondition::Learned(mins)) => format!("learned,{mins}"),
+        None => "disabled".to_string(),
+    }
+}
+
+fn occupancy_to_string(occupancy: &Option<OccupancyConfig>) -> String {
+    match occupancy {
+        Some(o) => format!("{},{},{}", o.before, o.after, o.off_after),
+        None => "disabled".to_string(),
+    }
+}
+
+fn limits_to_string(limits: &Option<TemperatureLimits>) -> String {
+    match limits {
+        Some(l) => format!("{},{}", l.min, l.max),
+        None => "default".to_string(),
+    }
+}
+
+fn keyframes_to_string(keyframes: &[Keyframe]) -> String {
+    if keyframes.is_empty() {
+        return "none".to_string();
+    }
+
+    keyframes
+        .iter()
+        .map(|k| format!("{}:{}:{}", k.at, k.temp, k.curve))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn temps_to_string(temps: &[f32]) -> String {
     temps
         .iter()
         .map(|e| e.to_string())
@@ -13,6 +13,7 @@ async fn test_load_solo_config() {
         }
         _ => panic!("Expected solo profile"),
     }
+    assert_eq!(config.presets["summer"].temperatures, vec![22., 24., 26.]);
 }
 
 #[tokio::test]
@@ -28,3 +29,147 @@ async fn test_load_couples_config() {
         _ => panic!("Expected couples profile"),
     }
 }
+
+fn weekday_profile() -> SideConfig {
+    SideConfig {
+        temperatures: vec![27.],
+        control: ControlMode::Water,
+        sleep: Time::constant(22, 0, 0, 0),
+        wake: Time::constant(6, 0, 0, 0),
+        alarm: None,
+        keyframes: vec![],
+        precondition: None,
+        occupancy: None,
+        schedules: vec![DaySchedule {
+            days: vec![Day::Friday, Day::Saturday],
+            temperatures: vec![25.],
+            sleep: Time::constant(0, 30, 0, 0),
+            wake: Time::constant(9, 0, 0, 0),
+            alarm: None,
+            keyframes: vec![],
+        }],
+        preset: None,
+        limits: None,
+    }
+}
+
+fn zoned(s: &str) -> Zoned {
+    format!("{s}[UTC]").parse().unwrap()
+}
+
+#[test]
+fn test_for_night() {
+    let prof = weekday_profile();
+    assert_eq!(prof.for_night(Day::Thursday).temperatures, vec![27.]);
+    assert_eq!(prof.for_night(Day::Friday).temperatures, vec![25.]);
+    assert_eq!(
+        prof.for_night(Day::Saturday).wake,
+        Time::constant(9, 0, 0, 0)
+    );
+}
+
+#[test]
+fn test_for_now() {
+    let prof = weekday_profile();
+    // 2025-01-03 is a Friday, Thursday night is still running
+    assert_eq!(
+        prof.for_now(&zoned("2025-01-03T05:00")).wake,
+        Time::constant(6, 0, 0, 0)
+    );
+    assert_eq!(
+        prof.for_now(&zoned("2025-01-03T07:00")).wake,
+        Time::constant(9, 0, 0, 0)
+    );
+    // Friday's doesn't cross midnight, so Saturday's applies from midnight
+    assert_eq!(
+        prof.for_now(&zoned("2025-01-04T01:00")).wake,
+        Time::constant(9, 0, 0, 0)
+    );
+    // Sunday night is back to the defaults
+    assert_eq!(
+        prof.for_now(&zoned("2025-01-05T23:00")).wake,
+        Time::constant(6, 0, 0, 0)
+    );
+}
+
+#[test]
+fn test_waking_today() {
+    let prof = weekday_profile();
+    // Friday: Thursday's crosses midnight, Friday's starts after midnight
+    let wakes: Vec<_> = prof
+        .waking_today(&zoned("2025-01-03T12:00"))
+        .iter()
+        .map(|c| c.wake)
+        .collect();
+    assert_eq!(
+        wakes,
+        vec![Time::constant(6, 0, 0, 0), Time::constant(9, 0, 0, 0)]
+    );
+
+    // Sunday: Saturday's doesn't cross midnight, Sunday's does
+    assert!(prof.waking_today(&zoned("2025-01-05T12:00")).is_empty());
+}
+
+#[test]
+fn test_day_schedule_mut() {
+    let mut prof = weekday_profile();
+    prof.day_schedule_mut(Day::Saturday).temperatures = vec![24.];
+    prof.day_schedule_mut(Day::Monday).sleep = Time::constant(21, 0, 0, 0);
+
+    assert_eq!(prof.for_night(Day::Friday).temperatures, vec![25.]);
+    assert_eq!(prof.for_night(Day::Saturday).temperatures, vec![24.]);
+    assert_eq!(
+        prof.for_night(Day::Saturday).wake,
+        Time::constant(9, 0, 0, 0)
+    );
+    assert_eq!(
+        prof.for_night(Day::Monday).sleep,
+        Time::constant(21, 0, 0, 0)
+    );
+    assert_eq!(prof.for_night(Day::Monday).temperatures, vec![27.]);
+    assert_eq!(prof.schedules.len(), 3);
+
+    prof.clear_day(Day::Friday);
+    prof.clear_day(Day::Monday);
+    assert_eq!(prof.for_night(Day::Friday).temperatures, vec![27.]);
+    assert_eq!(prof.schedules.len(), 1);
+}
+
+#[test]
+fn test_temperature_unit() {
+    let f = TemperatureUnit::Fahrenheit;
+    assert_eq!(f.to_celsius(212.0), 100.0);
+    assert_eq!(f.format_reading(2650), "79.7");
+    assert_eq!(TemperatureUnit::Celsius.format_reading(2650), "2650");
+
+    // config round trip
+    let mut profile = weekday_profile();
+    let original = profile.clone();
+    profile.map_temperatures(|t| f.to_celsius(t));
+    profile.map_temperatures(|t| f.convert_celsius(t));
+    assert_eq!(profile, original);
+}
+
+#[test]
+fn test_validate() {
+    let unit = TemperatureUnit::Celsius;
+    let mut profile = weekday_profile();
+    assert_eq!(profile.validate(unit), Ok(()));
+
+    // typo in a schedule
+    profile.schedules[0].temperatures = vec![300.];
+    assert!(profile.validate(unit).is_err());
+
+    profile.schedules.clear();
+    profile.limits = Some(TemperatureLimits { min: 20., max: 26. });
+    assert!(profile.validate(unit).is_err());
+    profile.limits = Some(TemperatureLimits { min: 5., max: 30. });
+    assert!(profile.validate(unit).is_err());
+
+    // float error from fahrenheit
+    let f = TemperatureUnit::Fahrenheit;
+    profile.limits = None;
+    profile.temperatures = vec![f.to_celsius(109.4), f.to_celsius(55.4)];
+    assert_eq!(profile.validate(f), Ok(()));
+    assert_eq!(TemperatureLimits::default().clamp(5000), 4300);
+}
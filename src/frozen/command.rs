@@ -5,7 +5,7 @@ use crate::{
         codec::{CommandTrait, command},
         packet::BedSide,
     },
-    frozen::packet::FrozenTarget,
+    frozen::packet::{FrozenTarget, MAX_TARGET_TEMP, MIN_TARGET_TEMP},
 };
 
 #[derive(Debug, Clone, Display, AsRefStr, IntoStaticStr)]
@@ -15,6 +15,10 @@ pub enum FrozenCommand {
     #[allow(dead_code)]
     GetFirmware,
     JumpToFirmware,
+    /// one-wire temperature sensors
+    GetSensors,
+    /// flash lock & calibration status
+    GetCalibration,
     #[allow(dead_code)]
     Prime,
     #[allow(dead_code)]
@@ -39,51 +43,44 @@ impl CommandTrait for FrozenCommand {
             GetTemperatures => command(vec![0x41]),
 
             /*
-
-            After sending 0x50 command, we get back:
-
-            Response In Test #1:
-            D0 00
-            28 FF C2 E9 A5 21 56 F3 07 FB
-            28 FF 3A CF 23 22 31 12 09 34
-            28 FF CE 0B 2C E2 23 56 0A 0F
-            28 FF 07 E5 2C E2 20 39 0A 0F
-
-            Response In Test #2:
-            D0 00
-            28 FF C2 E9 A5 21 56 F3 08 08
-            28 FF 3A CF 23 22 31 12 09 3A
-            28 FF CE 0B 2C E2 23 56 0A 15
-            28 FF 07 E5 2C E2 20 39 0A 15
-
-
-            <- GOT 0x50 RESPONSE SHOWN ABOVE #2 ->
-            Temperature update - Left: 2581, Right: 2581, Heatsink: 2362, Error: 8
-            Message: FW: pid[heatsink] 3.062500 0.693750 0.693750 0.000000 0.000000
-            Message: FW: pump[left] slow @ 6.030475V 0.169202A
-            Message: FW: pump[right] slow @ 6.044009V 0.161510A
-            Message: FW: pid[left] 25.812500 0.090498 -0.003750 0.094248 0.000000
-            Message: FW: pid[right] 25.812500 0.094561 -0.003750 0.098311 0.000000
-
+            0x50 -> D0 00, then 10 bytes per sensor (`FrozenPacket::Sensors`)
+            captures are in `frozen::packet` tests
+
+            the last 2 bytes are centidegrees celcius, they matched the next
+            TemperatureUpdate (Left: 2581, Right: 2581, Heatsink: 2362):
+            28 FF C2 E9 A5 21 56 F3 08 08   20.56C, unknown (ambient?)
+            28 FF 3A CF 23 22 31 12 09 3A   23.62C, heatsink
+            28 FF CE 0B 2C E2 23 56 0A 15   25.81C, water
+            28 FF 07 E5 2C E2 20 39 0A 15   25.81C, water
             */
-
+            GetSensors => command(vec![0x50]),
             /*
-            0x51 -> D1 00 (Flash/calibration status?)
+            0x51 -> D1 00 (`FrozenPacket::Calibration`), followed by:
 
             UTF-8 decode error: invalid utf-8 sequence of 1 bytes from index 16
             Message: FW: flash locked
             Message: FW: cal_info valid
 
+            the decode error is a message with binary data, likely the raw cal_info
             */
+            GetCalibration => command(vec![0x51]),
             Prime => command(vec![0x52]),
             Random(cmd) => command(vec![*cmd]),
-            SetTargetTemperature { side, tar } => command(vec![
-                0x40,
-                *side as u8,
-                tar.enabled as u8,
-                (tar.temp >> 8) as u8,
-                tar.temp as u8,
-            ]),
+            SetTargetTemperature { side, tar } => {
+                // last line of defense, config & MQTT are already validated.
+                // disabled targets are sent as is (usually 0)
+                let temp = match tar.enabled {
+                    true => tar.temp.clamp(MIN_TARGET_TEMP, MAX_TARGET_TEMP),
+                    false => tar.temp,
+                };
+                command(vec![
+                    0x40,
+                    *side as u8,
+                    tar.enabled as u8,
+                    (temp >> 8) as u8,
+                    temp as u8,
+                ])
+            }
         }
     }
 }
@@ -126,6 +123,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_getsensors() {
+        assert_eq!(
+            FrozenCommand::GetSensors.to_bytes(),
+            hex!("7E 01 50 96 69").to_vec()
+        );
+    }
+
+    #[test]
+    fn test_getcalibration() {
+        assert_eq!(
+            FrozenCommand::GetCalibration.to_bytes(),
+            hex!("7E 01 51 86 48").to_vec()
+        );
+    }
+
     #[test]
     fn test_prime() {
         assert_eq!(
@@ -145,4 +158,27 @@ mod tests {
         };
         assert_eq!(cmd.to_bytes(), hex!("7E 05 40 00 01 0E 10 E6 A8").to_vec());
     }
+
+    #[test]
+    fn test_temp_clamped() {
+        let cmd = |temp| FrozenCommand::SetTargetTemperature {
+            side: BedSide::Right,
+            tar: FrozenTarget {
+                enabled: true,
+                temp,
+            },
+        };
+        assert_eq!(cmd(30000).to_bytes(), cmd(MAX_TARGET_TEMP).to_bytes());
+        assert_eq!(cmd(0).to_bytes(), cmd(MIN_TARGET_TEMP).to_bytes());
+        assert_ne!(cmd(2000).to_bytes(), cmd(MIN_TARGET_TEMP).to_bytes());
+    }
+
+    #[test]
+    fn test_temp_disabled() {
+        let cmd = FrozenCommand::SetTargetTemperature {
+            side: BedSide::Left,
+            tar: FrozenTarget::default(),
+        };
+        assert_eq!(cmd.to_bytes(), hex!("7E 05 40 00 00 00 00 E0 A6").to_vec());
+    }
 }
@@ -0,0 +1,161 @@
+use tokio::time::Instant;
+
+use crate::{
+    common::packet::BedSide,
+    config::{ControlMode, SidesConfig},
+    frozen::packet::{FrozenTarget, MAX_TARGET_TEMP, MIN_TARGET_TEMP},
+    sensor::packet::TemperatureData,
+};
+
+/// water offset per degree of bed error
+const KP: f32 = 1.0;
+/// water offset per degree of bed error per second (1 degree per 10 minutes)
+const KI: f32 = 1.0 / 600.0;
+/// centidegrees celcius, limits the integral to prevent windup
+const MAX_INTEGRAL: f32 = 1000.0;
+/// readings further apart than this don't count towards the integral
+const MAX_DT: f32 = 60.0;
+
+/// Outer loop for `ControlMode::Bed`. Adjusts a side's water target
+/// so its bed surface reaches the profile's temperature.
+#[derive(Debug, Default)]
+pub struct BedController {
+    integral: f32,
+    /// centidegrees celcius, added to the bed setpoint to get the water target
+    offset: f32,
+    last_update: Option<Instant>,
+}
+
+impl BedController {
+    /// takes in a new bed reading, all temperatures are centidegrees celcius
+    pub fn update(&mut self, setpoint: u16, bed_temp: u16, now: Instant) {
+        let error = setpoint as f32 - bed_temp as f32;
+
+        if let Some(last) = self.last_update {
+            let dt = now.duration_since(last).as_secs_f32();
+            if dt <= MAX_DT {
+                self.integral =
+                    (self.integral + KI * error * dt).clamp(-MAX_INTEGRAL, MAX_INTEGRAL);
+            }
+        }
+        self.last_update = Some(now);
+
+        self.offset = KP * error + self.integral;
+        log::debug!(
+            "Bed control: setpoint {setpoint}, bed {bed_temp}, water offset {:.0}",
+            self.offset
+        );
+    }
+
+    /// centidegrees celcius, without readings this is just the setpoint
+    pub fn water_target(&self, setpoint: u16) -> u16 {
+        (setpoint as f32 + self.offset).clamp(MIN_TARGET_TEMP as f32, MAX_TARGET_TEMP as f32) as u16
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BedControllers {
+    left: BedController,
+    right: BedController,
+}
+
+impl BedControllers {
+    fn get_mut(&mut self, side: &BedSide) -> &mut BedController {
+        match side {
+            BedSide::Left => &mut self.left,
+            BedSide::Right => &mut self.right,
+        }
+    }
+
+    /// feeds new bed readings to sides using `ControlMode::Bed`
+    /// `wanted` is the side's bed setpoint
+    pub fn update(
+        &mut self,
+        sides_config: &SidesConfig,
+        wanted: impl Fn(&BedSide) -> FrozenTarget,
+        data: &TemperatureData,
+    ) {
+        let now = Instant::now();
+        for side in [BedSide::Left, BedSide::Right] {
+            let wanted = wanted(&side);
+            let controller = self.get_mut(&side);
+            if wanted.enabled && sides_config.get_side(&side).control == ControlMode::Bed {
+                controller.update(wanted.temp, data.bed_side(&side), now);
+            } else {
+                controller.reset();
+            }
+        }
+    }
+
+    /// converts a bed setpoint from the profile into a water target within the side's limits
+    pub fn apply(
+        &self,
+        side: &BedSide,
+        sides_config: &SidesConfig,
+        wanted: FrozenTarget,
+    ) -> FrozenTarget {
+        if !wanted.enabled || sides_config.get_side(side).control != ControlMode::Bed {
+            return wanted;
+        }
+
+        let controller = match side {
+            BedSide::Left => &self.left,
+            BedSide::Right => &self.right,
+        };
+        let limits = sides_config.get_side(side).limits();
+        FrozenTarget {
+            enabled: true,
+            temp: limits.clamp(controller.water_target(wanted.temp)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_no_readings() {
+        let c = BedController::default();
+        assert_eq!(c.water_target(2700), 2700);
+        assert_eq!(c.water_target(1000), MIN_TARGET_TEMP);
+        assert_eq!(c.water_target(5000), MAX_TARGET_TEMP);
+    }
+
+    #[test]
+    fn test_converges() {
+        // bed settles between the water & body temperature
+        let bed_temp = |water: u16| ((water as f32 + 3500.0) / 2.0) as u16;
+
+        let mut c = BedController::default();
+        let mut now = Instant::now();
+        let setpoint = 2800;
+        let mut water = c.water_target(setpoint);
+        for _ in 0..2000 {
+            c.update(setpoint, bed_temp(water), now);
+            water = c.water_target(setpoint);
+            now += Duration::from_secs(4);
+        }
+
+        assert!(bed_temp(water).abs_diff(setpoint) <= 10, "water {water}");
+        assert!(water < setpoint);
+    }
+
+    #[test]
+    fn test_clamped() {
+        let mut c = BedController::default();
+        let mut now = Instant::now();
+        for _ in 0..1000 {
+            c.update(1500, 3500, now);
+            now += Duration::from_secs(4);
+        }
+        assert_eq!(c.water_target(1500), MIN_TARGET_TEMP);
+        assert_eq!(c.integral, -MAX_INTEGRAL);
+    }
+}
@@ -0,0 +1,220 @@
+use rumqttc::AsyncClient;
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    common::packet::BedSide,
+    config::TemperatureUnit,
+    frozen::state::FrozenState,
+    mqtt::{SUCCESS, publish_guaranteed_wait, publish_result},
+};
+
+/// centidegrees celcius
+const MAX_HEATSINK_TEMP: u16 = 5500;
+/// degrees celcius, normally around 10
+const MAX_REPORTED_ERROR: u8 = 30;
+/// centidegrees celcius between a side's water and its target
+const MAX_DIVERGENCE: u16 = 500;
+/// heatsink & error must be bad for this long, ignores single bad readings
+const TRIP_TIME: Duration = Duration::from_secs(30);
+/// long enough to heat or cool across the whole range
+const DIVERGENCE_TIME: Duration = Duration::from_secs(60 * 60);
+
+pub const TOPIC_CLEAR_FAULT: &str = "opensleep/actions/clear_fault";
+const TOPIC_FAULT: &str = "opensleep/state/frozen/fault";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// centidegrees celcius
+    Heatsink(u16),
+    /// `TemperatureUpdate::error`
+    Error(u8),
+    /// centidegrees celcius
+    Diverged {
+        side: BedSide,
+        temp: u16,
+        target: u16,
+    },
+}
+
+impl Fault {
+    /// the published reason, temperatures in `unit`
+    fn describe(&self, unit: TemperatureUnit) -> String {
+        let deg = |temp| unit.display_reading(temp);
+        match self {
+            Fault::Heatsink(temp) => format!("heatsink over temperature ({})", deg(*temp)),
+            Fault::Error(error) => format!("reported error too high ({error})"),
+            Fault::Diverged { side, temp, target } => format!(
+                "{side:?} water stuck at {} with a target of {}",
+                deg(*temp),
+                deg(*target)
+            ),
+        }
+    }
+}
+
+/// Disables all targets once Frozen looks unhealthy, until cleared over MQTT
+#[derive(Debug, Default)]
+pub struct FaultSupervisor {
+    /// when each condition started
+    heatsink_since: Option<Instant>,
+    error_since: Option<Instant>,
+    left_since: Option<Instant>,
+    right_since: Option<Instant>,
+    /// & the published reason
+    fault: Option<(Fault, String)>,
+}
+
+impl FaultSupervisor {
+    pub fn is_tripped(&self) -> bool {
+        self.fault.is_some()
+    }
+
+    pub async fn publish_reset(&self, client: &mut AsyncClient) {
+        publish_guaranteed_wait(client, TOPIC_FAULT, true, "none").await;
+    }
+
+    /// checks the latest state, returns if it just tripped
+    pub async fn update(&mut self, client: &mut AsyncClient, state: &FrozenState) -> bool {
+        if self.is_tripped() {
+            return false;
+        }
+        let Some(fault) = self.check(Instant::now(), state) else {
+            return false;
+        };
+
+        let reason = fault.describe(state.unit);
+        log::error!("Fault: {reason}, disabling all targets");
+        publish_guaranteed_wait(client, TOPIC_FAULT, true, reason.clone()).await;
+        self.fault = Some((fault, reason));
+        true
+    }
+
+    pub async fn clear(&mut self, client: &mut AsyncClient) {
+        let msg = match self.fault.take() {
+            Some((_, reason)) => format!("cleared fault: {reason}"),
+            None => "no fault to clear".to_string(),
+        };
+        log::info!("{msg}");
+        *self = Self::default();
+        self.publish_reset(client).await;
+        publish_result(client, "clear_fault", SUCCESS, msg).await;
+    }
+
+    fn check(&mut self, now: Instant, state: &FrozenState) -> Option<Fault> {
+        let u = state.temp.as_ref()?;
+
+        if held(
+            &mut self.heatsink_since,
+            u.heatsink_temp > MAX_HEATSINK_TEMP,
+            now,
+            TRIP_TIME,
+        ) {
+            return Some(Fault::Heatsink(u.heatsink_temp));
+        }
+
+        if held(
+            &mut self.error_since,
+            u.error > MAX_REPORTED_ERROR,
+            now,
+            TRIP_TIME,
+        ) {
+            return Some(Fault::Error(u.error));
+        }
+
+        for (side, since, temp, target) in [
+            (
+                BedSide::Left,
+                &mut self.left_since,
+                u.left_temp,
+                &state.left_target,
+            ),
+            (
+                BedSide::Right,
+                &mut self.right_since,
+                u.right_temp,
+                &state.right_target,
+            ),
+        ] {
+            // priming moves the water around regardless of the target
+            let target = target.as_ref().filter(|t| t.enabled && !state.is_priming);
+            let diverged = target.is_some_and(|t| t.temp.abs_diff(temp) > MAX_DIVERGENCE);
+            if held(since, diverged, now, DIVERGENCE_TIME) {
+                return Some(Fault::Diverged {
+                    side,
+                    temp,
+                    target: target.unwrap().temp,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+/// returns if `active` has been true for at least `time`
+fn held(since: &mut Option<Instant>, active: bool, now: Instant, time: Duration) -> bool {
+    if !active {
+        *since = None;
+        return false;
+    }
+    now.duration_since(*since.get_or_insert(now)) >= time
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frozen::packet::{FrozenTarget, TemperatureUpdate};
+
+    #[test]
+    fn test_trips() {
+        let mut state = FrozenState {
+            temp: Some(TemperatureUpdate {
+                left_temp: 2000,
+                right_temp: 3000,
+                heatsink_temp: 2300,
+                error: 9,
+                count: 0,
+            }),
+            left_target: Some(FrozenTarget {
+                enabled: true,
+                temp: 3000,
+            }),
+            ..Default::default()
+        };
+        let mut s = FaultSupervisor::default();
+        let mut now = Instant::now();
+        assert_eq!(s.check(now, &state), None);
+
+        // still heating up
+        now += DIVERGENCE_TIME / 2;
+        assert_eq!(s.check(now, &state), None);
+        now += DIVERGENCE_TIME / 2;
+        assert_eq!(
+            s.check(now, &state),
+            Some(Fault::Diverged {
+                side: BedSide::Left,
+                temp: 2000,
+                target: 3000,
+            })
+        );
+
+        // a single hot reading
+        let mut s = FaultSupervisor::default();
+        state.left_target = None;
+        state.temp.as_mut().unwrap().heatsink_temp = 6000;
+        assert_eq!(s.check(now, &state), None);
+        state.temp.as_mut().unwrap().heatsink_temp = 2300;
+        assert_eq!(s.check(now + TRIP_TIME, &state), None);
+
+        state.temp.as_mut().unwrap().heatsink_temp = 6000;
+        assert_eq!(s.check(now, &state), None);
+        assert_eq!(
+            s.check(now + TRIP_TIME, &state),
+            Some(Fault::Heatsink(6000))
+        );
+        assert_eq!(
+            Fault::Heatsink(6000).describe(TemperatureUnit::Fahrenheit),
+            "heatsink over temperature (140.0F)"
+        );
+    }
+}
@@ -1,17 +1,28 @@
+use crate::bus::{Bus, FrozenEvent, SensorEvent, Snapshot};
 use crate::common::{
-    codec::PacketCodec,
+    codec::{CodecStats, PacketCodec},
     packet::BedSide,
     serial::{SerialError, create_framed_port},
 };
 use crate::config::{Config, SidesConfig};
-use crate::frozen::{FrozenCommand, FrozenPacket, packet::FrozenTarget, state::FrozenState};
+use crate::frozen::{
+    FrozenAction, FrozenCommand, FrozenPacket,
+    control::BedControllers,
+    fault::FaultSupervisor,
+    manual::ManualOverrides,
+    occupancy::Occupancy,
+    packet::FrozenTarget,
+    precondition::Preconditioner,
+    priming::PrimingController,
+    state::{FrozenState, WaterTank},
+};
 use crate::led::{IS31FL3194Config, IS31FL3194Controller};
 use futures_util::{SinkExt, StreamExt, stream::SplitSink};
-use jiff::{SignedDuration, Timestamp, civil::Time, tz::TimeZone};
+use jiff::{Timestamp, tz::TimeZone};
 use linux_embedded_hal::I2cdev;
 use rumqttc::AsyncClient;
 use thiserror::Error;
-use tokio::sync::watch;
+use tokio::sync::{broadcast::error::RecvError, mpsc, watch};
 use tokio::time::{Duration, Instant, interval, sleep};
 use tokio_serial::SerialStream;
 use tokio_util::codec::Framed;
@@ -28,7 +39,6 @@ struct CommandTimers {
     last_hwinfo: Instant,
     last_left_temp: Instant,
     last_right_temp: Instant,
-    last_prime: Instant,
 }
 
 #[derive(Error, Debug)]
@@ -44,6 +54,8 @@ type Writer = SplitSink<Framed<SerialStream, PacketCodec<FrozenPacket>>, FrozenC
 pub async fn run(
     port: &'static str,
     mut config_rx: watch::Receiver<Config>,
+    bus: Bus,
+    mut action_rx: mpsc::Receiver<FrozenAction>,
     mut led: IS31FL3194Controller<I2cdev>,
     mut client: AsyncClient,
 ) -> Result<(), FrozenError> {
@@ -52,17 +64,34 @@ pub async fn run(
     let cfg = config_rx.borrow_and_update();
     let led_idle = cfg.led.idle.get_config(cfg.led.band.clone());
     let led_active = cfg.led.active.get_config(cfg.led.band.clone());
+    let led_fault = cfg.led.fault.get_config(cfg.led.band.clone());
     set_led(&mut led, &led_idle);
     let timezone = cfg.timezone.clone();
     let mut away_mode = cfg.away_mode;
     let mut prime = cfg.prime;
+    let mut pause_without_water = cfg.pause_without_water;
     let mut side_config = cfg.profile.clone();
+    let unit = cfg.unit;
     drop(cfg);
 
-    let (mut writer, mut reader) = create_framed_port::<FrozenPacket>(port, BAUD)?.split();
+    // before anything slow, so no events are missed
+    let mut sensor_events = bus.subscribe_sensor();
+    let mut snapshot = bus.snapshot();
+    let mut occupancy = Occupancy::default();
+    if let Some(presence) = &snapshot.borrow_and_update().presence {
+        occupancy.update_presence(presence);
+    }
+
+    let (mut writer, mut reader) =
+        create_framed_port::<FrozenPacket>(port, BAUD, CodecStats::default())?.split();
 
-    let mut state = FrozenState::default();
+    let mut state = FrozenState {
+        unit,
+        ..Default::default()
+    };
     state.publish_reset(&mut client).await;
+    let mut faults = FaultSupervisor::default();
+    faults.publish_reset(&mut client).await;
 
     // grab hwinfo @ boot
     send_command(&mut writer, FrozenCommand::Ping).await;
@@ -73,22 +102,38 @@ pub async fn run(
     let mut timers = CommandTimers::default();
     let mut was_active = false;
     let mut wake_attempts = 0;
+    let mut bed_control = BedControllers::default();
+    let mut overrides = ManualOverrides::default();
+    let mut precondition = Preconditioner::default();
+    let mut priming = PrimingController::default();
 
     loop {
         tokio::select! {
             Some(result) = reader.next() => match result {
                 Ok(packet) => {
+                    let prev = state.clone();
+                    priming.handle_packet(Instant::now(), &packet);
+                    state.is_priming = priming.is_active();
                     state.handle_packet(&mut client, packet).await;
+                    send_events(&bus, &prev, &state);
+                    if prev.water_tank != state.water_tank {
+                        priming.set_tank_out(state.water_tank == WaterTank::Empty);
+                    }
+                    precondition.update(&state);
+                    if faults.update(&mut client, &state).await {
+                        set_led(&mut led, &led_fault);
+                    }
 
                     if state.is_active() != was_active {
+                        was_active = !was_active;
                         if was_active {
-                            log::info!("Profile ended!");
-                            set_led(&mut led, &led_idle);
-                        } else {
                             log::info!("Starting profile!");
-                            set_led(&mut led, &led_active);
+                        } else {
+                            log::info!("Profile ended!");
+                        }
+                        if !faults.is_tripped() {
+                            set_led(&mut led, if was_active { &led_active } else { &led_idle });
                         }
-                        was_active = !was_active;
                     }
                 }
                 Err(e) => {
@@ -98,14 +143,48 @@ pub async fn run(
 
             // sends commands separated by 20ms
             // before sending any commands, wakes the device by sending ping + jump fw
-            _ = interval.tick() => if let Some(cmd) = get_next_command(
-                &mut timers,
-                &state,
-                &timezone,
-                &away_mode,
-                &prime,
-                &side_config
-            ) {
+            _ = interval.tick() => {
+                overrides.update(&mut client);
+                let now_local = Timestamp::now().to_zoned(timezone.clone());
+                occupancy.update(&now_local, &side_config);
+                priming.update(
+                    Instant::now(),
+                    now_local.time(),
+                    prime,
+                    away_mode,
+                    state.is_active(),
+                    is_occupied(&snapshot),
+                );
+                state.is_priming = priming.is_active();
+                bus.send_frozen(FrozenEvent::Priming(state.is_priming));
+                priming.publish(&mut client).await;
+
+                // pause temperature control, the pumps would run dry
+                let tank_out = pause_without_water && state.water_tank == WaterTank::Empty;
+                let Some(cmd) = get_next_command(
+                    &mut timers,
+                    &state,
+                    &mut priming,
+                    |side| match faults.is_tripped() || tank_out {
+                        true => FrozenTarget::default(),
+                        false => bed_control.apply(
+                            side,
+                            &side_config,
+                            wanted_target(
+                                &timezone,
+                                &away_mode,
+                                &side_config,
+                                &overrides,
+                                &precondition,
+                                &occupancy,
+                                side,
+                            ),
+                        ),
+                    },
+                ) else {
+                    continue;
+                };
+
                 let now = Instant::now();
 
                 // ready to send command
@@ -131,36 +210,140 @@ pub async fn run(
                         log::error!("Failed to send JumpToFirmware: {e}");
                     }
                 }
+            }
+
+            Ok(()) = snapshot.changed() => {
+                if let Some(presence) = &snapshot.borrow_and_update().presence {
+                    occupancy.update_presence(presence);
+                }
+            }
+
+            result = sensor_events.recv() => match result {
+                Ok(SensorEvent::BedTemperature(data)) => {
+                    bed_control.update(
+                        &side_config,
+                        |side| wanted_target(
+                            &timezone,
+                            &away_mode,
+                            &side_config,
+                            &overrides,
+                            &precondition,
+                            &occupancy,
+                            side,
+                        ),
+                        &data,
+                    );
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => log::warn!("Missed {n} sensor events"),
+                // never, `bus` holds the sender
+                Err(RecvError::Closed) => {}
+            },
+
+            Some(action) = action_rx.recv() => match action {
+                FrozenAction::Override(req) => {
+                    overrides
+                        .handle_request(&mut client, req, &side_config, state.unit)
+                        .await;
+                }
+                FrozenAction::ClearFault => {
+                    faults.clear(&mut client).await;
+                    set_led(&mut led, if was_active { &led_active } else { &led_idle });
+                }
+                FrozenAction::PrimeNow => {
+                    let occupied = is_occupied(&snapshot);
+                    priming.prime_now(&mut client, state.is_active(), occupied).await;
+                }
             },
 
             Ok(_) = config_rx.changed() => {
                 let cfg = config_rx.borrow();
                 away_mode = cfg.away_mode;
                 prime = cfg.prime;
+                pause_without_water = cfg.pause_without_water;
                 side_config = cfg.profile.clone();
+                state.unit = cfg.unit;
             }
         }
     }
 }
 
-fn get_next_command(
-    timers: &mut CommandTimers,
-    state: &FrozenState,
+/// shares what changed in `state` on the bus
+fn send_events(bus: &Bus, prev: &FrozenState, state: &FrozenState) {
+    if let Some(u) = &state.temp
+        && prev.temp.as_ref() != Some(u)
+    {
+        bus.send_frozen(FrozenEvent::WaterTemperature(u.clone()));
+    }
+    for (side, target) in [
+        (BedSide::Left, &state.left_target),
+        (BedSide::Right, &state.right_target),
+    ] {
+        if let Some(target) = target {
+            bus.send_frozen(FrozenEvent::Target {
+                side,
+                target: target.clone(),
+            });
+        }
+    }
+    bus.send_frozen(FrozenEvent::Priming(state.is_priming));
+}
+
+/// if anyone is in bed, `false` until presence is calibrated
+fn is_occupied(snapshot: &watch::Receiver<Snapshot>) -> bool {
+    snapshot.borrow().presence.as_ref().is_some_and(|p| p.any)
+}
+
+/// a manual override if active, otherwise the profile's target
+fn wanted_target(
     timezone: &TimeZone,
     away_mode: &bool,
-    prime_time: &Time,
     side_config: &SidesConfig,
+    overrides: &ManualOverrides,
+    precondition: &Preconditioner,
+    occupancy: &Occupancy,
+    side: &BedSide,
+) -> FrozenTarget {
+    overrides.get(side).unwrap_or_else(|| {
+        FrozenTarget::calc_wanted(
+            timezone,
+            away_mode,
+            side_config,
+            side,
+            precondition,
+            occupancy,
+        )
+    })
+}
+
+/// `wanted` is the water target for a side
+fn get_next_command(
+    timers: &mut CommandTimers,
+    state: &FrozenState,
+    priming: &mut PrimingController,
+    wanted: impl Fn(&BedSide) -> FrozenTarget,
 ) -> Option<FrozenCommand> {
     let now = Instant::now();
 
-    if state.hardware_info.is_none() && now.duration_since(timers.last_hwinfo) > HWINFO_INT {
-        timers.last_hwinfo = now;
-        return Some(FrozenCommand::GetHardwareInfo);
+    // hardware diagnostics, once at startup
+    if now.duration_since(timers.last_hwinfo) > HWINFO_INT {
+        let cmd = if state.hardware_info.is_none() {
+            Some(FrozenCommand::GetHardwareInfo)
+        } else if state.sensors.is_none() {
+            Some(FrozenCommand::GetSensors)
+        } else if state.calibration.is_none() {
+            Some(FrozenCommand::GetCalibration)
+        } else {
+            None
+        };
+        if cmd.is_some() {
+            timers.last_hwinfo = now;
+            return cmd;
+        }
     }
 
     if now.duration_since(timers.last_left_temp) > TEMP_INT {
-        let wanted_left =
-            FrozenTarget::calc_wanted(timezone, away_mode, side_config, &BedSide::Left);
+        let wanted_left = wanted(&BedSide::Left);
         timers.last_left_temp = now;
         if state.left_target.as_ref() != Some(&wanted_left) {
             return Some(FrozenCommand::SetTargetTemperature {
@@ -171,8 +354,7 @@ fn get_next_command(
     }
 
     if now.duration_since(timers.last_right_temp) > TEMP_INT {
-        let wanted_right =
-            FrozenTarget::calc_wanted(timezone, away_mode, side_config, &BedSide::Right);
+        let wanted_right = wanted(&BedSide::Right);
         timers.last_right_temp = now;
 
         if state.right_target.as_ref() != Some(&wanted_right) {
@@ -183,19 +365,7 @@ fn get_next_command(
         }
     }
 
-    let now_local = Timestamp::now().to_zoned(timezone.clone()).time();
-
-    // TODO verify it actually started priming
-    if !away_mode
-        // prime if we are within 30 seconds of prime time AND we havn't tried to prime in the last minute
-        && now.duration_since(timers.last_prime) > Duration::from_secs(60)
-        && now_local.duration_until(*prime_time).abs() < SignedDuration::from_secs(30)
-    {
-        timers.last_prime = now;
-        return Some(FrozenCommand::Prime);
-    }
-
-    None
+    priming.next_command(now)
 }
 
 async fn send_command(writer: &mut Writer, cmd: FrozenCommand) {
@@ -221,7 +391,6 @@ impl Default for CommandTimers {
             last_hwinfo: now,
             last_left_temp: ago,
             last_right_temp: ago,
-            last_prime: ago,
         }
     }
 }
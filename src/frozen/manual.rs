@@ -0,0 +1,237 @@
+use rumqttc::AsyncClient;
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    common::packet::BedSide,
+    config::{SidesConfig, TemperatureUnit},
+    frozen::packet::FrozenTarget,
+    mqtt::{ERROR, SUCCESS, publish_high_freq, publish_result},
+};
+
+const PUBLISH_INT: Duration = Duration::from_secs(10);
+/// longest override, a day
+const MAX_MINUTES: u64 = 24 * 60;
+
+pub const TOPIC_SET_OVERRIDE: &str = "opensleep/actions/set_override";
+const TOPIC_LEFT_OVERRIDE: &str = "opensleep/state/frozen/left_override";
+const TOPIC_RIGHT_OVERRIDE: &str = "opensleep/state/frozen/right_override";
+
+/// Parsed `set_override` payload: `TARGET=TEMP,MINUTES` or `TARGET=cancel`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverrideRequest {
+    pub left: bool,
+    pub right: bool,
+    /// centidegrees celcius & how long to hold it, `None` cancels
+    pub hold: Option<(u16, Duration)>,
+}
+
+impl OverrideRequest {
+    /// `TEMP` is in `unit`
+    pub fn parse(s: &str, unit: TemperatureUnit) -> Result<Self, String> {
+        let (target, value) = s
+            .trim()
+            .split_once('=')
+            .ok_or("Invalid input. Requires `TARGET=TEMP,MINUTES` or `TARGET=cancel`")?;
+
+        let (left, right) = match target.trim() {
+            "left" => (true, false),
+            "right" => (false, true),
+            "both" => (true, true),
+            _ => return Err("Invalid TARGET. Must be `left`, `right`, or `both`".to_string()),
+        };
+
+        let value = value.trim();
+        if value == "cancel" {
+            return Ok(Self {
+                left,
+                right,
+                hold: None,
+            });
+        }
+
+        let (temp, minutes) = value
+            .split_once(',')
+            .ok_or("Invalid value. Requires `TEMP,MINUTES` or `cancel`")?;
+        let temp: f32 = temp
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid temperature: {e}"))?;
+        let minutes: u64 = minutes
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid minutes: {e}"))?;
+        if !(1..=MAX_MINUTES).contains(&minutes) {
+            return Err(format!("Invalid minutes: must be 1 to {MAX_MINUTES}"));
+        }
+
+        Ok(Self {
+            left,
+            right,
+            // NOTE: also converts celcius -> centideg celcius
+            hold: Some((
+                (unit.to_celsius(temp) * 100.0).round() as u16,
+                Duration::from_secs(minutes * 60),
+            )),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ActiveOverride {
+    /// centidegrees celcius
+    temp: u16,
+    until: Instant,
+}
+
+/// Fixed targets that take precedence over the profile until they expire
+#[derive(Debug)]
+pub struct ManualOverrides {
+    left: Option<ActiveOverride>,
+    right: Option<ActiveOverride>,
+    last_publish: Instant,
+}
+
+impl Default for ManualOverrides {
+    fn default() -> Self {
+        Self {
+            left: None,
+            right: None,
+            // publish right away
+            last_publish: Instant::now() - PUBLISH_INT,
+        }
+    }
+}
+
+impl ManualOverrides {
+    fn get_mut(&mut self, side: &BedSide) -> &mut Option<ActiveOverride> {
+        match side {
+            BedSide::Left => &mut self.left,
+            BedSide::Right => &mut self.right,
+        }
+    }
+
+    /// rejects temperatures outside of the side's limits
+    pub async fn handle_request(
+        &mut self,
+        client: &mut AsyncClient,
+        req: OverrideRequest,
+        sides_config: &SidesConfig,
+        unit: TemperatureUnit,
+    ) {
+        let now = Instant::now();
+        let sides = [(req.left, BedSide::Left), (req.right, BedSide::Right)];
+        let sides = sides.iter().filter(|(t, _)| *t).map(|(_, s)| s);
+
+        if let Some((temp, _)) = req.hold {
+            let temp = temp as f32 / 100.0;
+            for side in sides.clone() {
+                let limits = sides_config.get_side(side).limits();
+                if !limits.contains(temp) {
+                    let msg = format!(
+                        "temperature {} is outside of the {side:?} side's limits ({})",
+                        unit.convert_celsius(temp),
+                        limits.display(unit),
+                    );
+                    log::error!("Manual override rejected: {msg}");
+                    publish_result(client, "set_override", ERROR, msg).await;
+                    return;
+                }
+            }
+        }
+
+        for side in sides {
+            *self.get_mut(side) = req.hold.map(|(temp, duration)| ActiveOverride {
+                temp,
+                until: now + duration,
+            });
+        }
+
+        let msg = match req.hold {
+            Some((temp, duration)) => format!(
+                "holding {} for {} minutes",
+                unit.display_reading(temp),
+                duration.as_secs() / 60
+            ),
+            None => "cancelled override".to_string(),
+        };
+        log::info!("Manual override: {msg}");
+        // show new state immediately
+        self.last_publish = now - PUBLISH_INT;
+        publish_result(client, "set_override", SUCCESS, msg).await;
+    }
+
+    /// the overridden target, if active
+    pub fn get(&self, side: &BedSide) -> Option<FrozenTarget> {
+        let active = match side {
+            BedSide::Left => self.left,
+            BedSide::Right => self.right,
+        }?;
+        (Instant::now() < active.until).then_some(FrozenTarget {
+            enabled: true,
+            temp: active.temp,
+        })
+    }
+
+    /// expires overrides & publishes remaining time every `PUBLISH_INT`
+    pub fn update(&mut self, client: &mut AsyncClient) {
+        let now = Instant::now();
+        if now.duration_since(self.last_publish) < PUBLISH_INT {
+            return;
+        }
+        self.last_publish = now;
+
+        for (side, topic) in [
+            (BedSide::Left, TOPIC_LEFT_OVERRIDE),
+            (BedSide::Right, TOPIC_RIGHT_OVERRIDE),
+        ] {
+            let active = self.get_mut(&side);
+            if active.is_some_and(|a| now >= a.until) {
+                log::info!("Manual override expired for {side:?}, returning to profile");
+                *active = None;
+            }
+
+            let payload = match active {
+                Some(a) => a.until.duration_since(now).as_secs().to_string(),
+                None => "disabled".to_string(),
+            };
+            publish_high_freq(client, topic, payload);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let parse = |s| OverrideRequest::parse(s, TemperatureUnit::Celsius);
+        assert_eq!(
+            parse("left=24,120"),
+            Ok(OverrideRequest {
+                left: true,
+                right: false,
+                hold: Some((2400, Duration::from_secs(7200))),
+            })
+        );
+        assert_eq!(
+            parse("both=cancel"),
+            Ok(OverrideRequest {
+                left: true,
+                right: true,
+                hold: None,
+            })
+        );
+        assert!(parse("left=24").is_err());
+        assert!(parse("left=24,0").is_err());
+        assert!(parse("left=24,1441").is_err());
+        assert!(parse("left=24,200000000000000000").is_err());
+        assert!(parse("left=24,1440").is_ok());
+        assert!(parse("middle=24,30").is_err());
+        assert_eq!(
+            OverrideRequest::parse("right=75.2,30", TemperatureUnit::Fahrenheit)
+                .map(|r| r.hold.unwrap().0),
+            Ok(2400)
+        );
+    }
+}
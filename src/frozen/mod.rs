@@ -1,9 +1,26 @@
 pub mod command;
+mod control;
+pub mod fault;
 pub mod manager;
+pub mod manual;
+mod occupancy;
 pub mod packet;
+mod precondition;
+pub mod priming;
 mod profile;
 pub mod state;
+mod telemetry;
 
 pub use command::FrozenCommand;
 pub use manager::{PORT, run};
 pub use packet::FrozenPacket;
+
+use manual::OverrideRequest;
+
+/// MQTT actions handled by the Frozen task
+#[derive(Debug)]
+pub enum FrozenAction {
+    Override(OverrideRequest),
+    ClearFault,
+    PrimeNow,
+}
@@ -0,0 +1,242 @@
+use jiff::{SignedDuration, Zoned, civil::Time};
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    common::packet::BedSide,
+    config::{SideConfig, SidesConfig},
+    frozen::profile::forward_duration,
+    sensor::presence::PresenceState,
+};
+
+/// When a side's profile starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProfileStart {
+    /// at `sleep`, no `occupancy` config
+    Scheduled,
+    /// once the side is occupied
+    Waiting,
+    Started(Time),
+    /// started, but the side has been empty for too long
+    Off,
+}
+
+#[derive(Debug, Default)]
+struct SideOccupancy {
+    occupied: bool,
+    /// when the side was last left, `None` while occupied
+    empty_since: Option<Instant>,
+    started: Option<Time>,
+    off: bool,
+}
+
+impl SideOccupancy {
+    fn update_presence(&mut self, occupied: bool, at: Instant) {
+        if occupied {
+            self.empty_since = None;
+        } else if self.occupied {
+            self.empty_since = Some(at);
+        }
+        self.occupied = occupied;
+    }
+
+    fn update(&mut self, side: &BedSide, cfg: &SideConfig, now: Time, at: Instant) {
+        let Some(occupancy) = &cfg.occupancy else {
+            self.started = None;
+            self.off = false;
+            return;
+        };
+
+        if let Some(start) = self.started
+            && forward_duration(start, now) > forward_duration(start, cfg.wake)
+        {
+            log::info!("Occupancy triggered profile ended for {side:?}");
+            self.started = None;
+        }
+
+        let before = SignedDuration::from_mins(occupancy.before as i64);
+        let window = before + SignedDuration::from_mins(occupancy.after as i64);
+        let in_window = forward_duration(cfg.sleep.wrapping_sub(before), now) <= window;
+        if self.started.is_none() && self.occupied && in_window {
+            log::info!("{side:?} occupied, starting profile");
+            self.started = Some(now);
+        }
+
+        let off_after = Duration::from_secs(occupancy.off_after as u64 * 60);
+        let off = self.started.is_some()
+            && !self.occupied
+            && self
+                .empty_since
+                .is_none_or(|t| at.duration_since(t) >= off_after);
+        if off != self.off {
+            match off {
+                true => log::info!("{side:?} empty for {} minutes, off", occupancy.off_after),
+                false => log::info!("{side:?} occupied again, resuming profile"),
+            }
+            self.off = off;
+        }
+    }
+
+    fn start(&self, cfg: &SideConfig) -> ProfileStart {
+        match (cfg.occupancy, self.started) {
+            (None, _) => ProfileStart::Scheduled,
+            (Some(_), None) => ProfileStart::Waiting,
+            (Some(_), Some(_)) if self.off => ProfileStart::Off,
+            (Some(_), Some(start)) => ProfileStart::Started(start),
+        }
+    }
+}
+
+/// how often the windows are checked, presence is applied right away
+const UPDATE_INT: Duration = Duration::from_secs(1);
+
+/// Presence-triggered profile start & stop (`SideConfig::occupancy`)
+#[derive(Debug, Default)]
+pub struct Occupancy {
+    left: SideOccupancy,
+    right: SideOccupancy,
+    last_update: Option<Instant>,
+}
+
+impl Occupancy {
+    fn get(&self, side: &BedSide) -> &SideOccupancy {
+        match side {
+            BedSide::Left => &self.left,
+            BedSide::Right => &self.right,
+        }
+    }
+
+    pub fn update_presence(&mut self, presence: &PresenceState) {
+        let now = Instant::now();
+        self.left.update_presence(presence.left, now);
+        self.right.update_presence(presence.right, now);
+    }
+
+    pub fn update(&mut self, now: &Zoned, sides_config: &SidesConfig) {
+        let at = Instant::now();
+        if self
+            .last_update
+            .is_some_and(|t| at.duration_since(t) < UPDATE_INT)
+        {
+            return;
+        }
+        self.last_update = Some(at);
+
+        for (side, occupancy) in [
+            (BedSide::Left, &mut self.left),
+            (BedSide::Right, &mut self.right),
+        ] {
+            let cfg = sides_config.get_side(&side).for_now(now);
+            occupancy.update(&side, &cfg, now.time(), at);
+        }
+    }
+
+    pub fn start(&self, side: &BedSide, cfg: &SideConfig) -> ProfileStart {
+        self.get(side).start(cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::civil::time;
+
+    use super::*;
+    use crate::config::{ControlMode, OccupancyConfig};
+
+    fn config(off_after: u32) -> SideConfig {
+        SideConfig {
+            temperatures: vec![27.0],
+            control: ControlMode::Water,
+            sleep: time(22, 0, 0, 0),
+            wake: time(6, 0, 0, 0),
+            alarm: None,
+            keyframes: vec![],
+            precondition: None,
+            occupancy: Some(OccupancyConfig {
+                before: 60,
+                after: 120,
+                off_after,
+            }),
+            schedules: vec![],
+            preset: None,
+            limits: None,
+        }
+    }
+
+    #[test]
+    fn test_start_stop() {
+        let cfg = config(0);
+        let side = BedSide::Left;
+        // occupied outside of the window
+        let at = Instant::now();
+        let mut o = SideOccupancy::default();
+        o.update_presence(true, at);
+        o.update(&side, &cfg, time(20, 0, 0, 0), at);
+        assert_eq!(o.start(&cfg), ProfileStart::Waiting);
+
+        o.update(&side, &cfg, time(21, 30, 0, 0), at);
+        assert_eq!(o.start(&cfg), ProfileStart::Started(time(21, 30, 0, 0)));
+
+        o.update_presence(false, at);
+        o.update(&side, &cfg, time(23, 0, 0, 0), at);
+        assert_eq!(o.start(&cfg), ProfileStart::Off);
+
+        o.update_presence(true, at);
+        o.update(&side, &cfg, time(2, 0, 0, 0), at);
+        assert_eq!(o.start(&cfg), ProfileStart::Started(time(21, 30, 0, 0)));
+
+        o.update(&side, &cfg, time(6, 30, 0, 0), at);
+        assert_eq!(o.start(&cfg), ProfileStart::Waiting);
+    }
+
+    #[test]
+    fn test_empty_since() {
+        let cfg = config(10);
+        let side = BedSide::Right;
+        let at = Instant::now();
+        let mut o = SideOccupancy::default();
+        o.update_presence(true, at);
+        o.update(&side, &cfg, time(22, 0, 0, 0), at);
+
+        // repeated presence doesn't move when the side was left
+        let left_at = at + Duration::from_secs(3 * 60 * 60);
+        o.update_presence(false, left_at);
+        o.update_presence(false, left_at + Duration::from_secs(60));
+        assert_eq!(o.empty_since, Some(left_at));
+
+        o.update(
+            &side,
+            &cfg,
+            time(1, 5, 0, 0),
+            left_at + Duration::from_secs(5 * 60),
+        );
+        assert_eq!(o.start(&cfg), ProfileStart::Started(time(22, 0, 0, 0)));
+        o.update(
+            &side,
+            &cfg,
+            time(1, 10, 0, 0),
+            left_at + Duration::from_secs(10 * 60),
+        );
+        assert_eq!(o.start(&cfg), ProfileStart::Off);
+    }
+
+    #[test]
+    fn test_short_exit() {
+        let cfg = config(15);
+        let side = BedSide::Left;
+        let at = Instant::now();
+        let mins = |m: u64| at + Duration::from_secs(m * 60);
+        let mut o = SideOccupancy::default();
+        o.update_presence(true, at);
+        o.update(&side, &cfg, time(22, 0, 0, 0), at);
+
+        // up for a couple of minutes in the night
+        o.update_presence(false, mins(120));
+        o.update(&side, &cfg, time(0, 1, 0, 0), mins(121));
+        assert_eq!(o.start(&cfg), ProfileStart::Started(time(22, 0, 0, 0)));
+        o.update_presence(true, mins(122));
+
+        // well past `off_after` since leaving, but back in bed
+        o.update(&side, &cfg, time(0, 30, 0, 0), mins(150));
+        assert_eq!(o.start(&cfg), ProfileStart::Started(time(22, 0, 0, 0)));
+    }
+}
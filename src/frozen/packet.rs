@@ -1,7 +1,10 @@
+use std::fmt;
+
 use bytes::BytesMut;
 
 use crate::common::packet::{
-    self, BedSide, HardwareInfo, Packet, PacketError, invalid_structure, validate_packet_size,
+    self, BedSide, HardwareInfo, Packet, PacketError, invalid_structure, validate_packet_at_least,
+    validate_packet_size,
 };
 
 #[derive(Debug, PartialEq)]
@@ -19,8 +22,17 @@ pub enum FrozenPacket {
     GetTemperature(GetTemperature),
     PrimingStarted,
     GetFirmware,
+    /// response to `GetSensors`
+    Sensors(Vec<OneWireSensor>),
+    /// response to `GetCalibration`, unknown value, always 0.
+    /// followed by `FW: flash ..` & `FW: cal_info ..` messages
+    Calibration(u8),
 }
 
+/// centidegrees celcius, hard limits of any water target
+pub const MIN_TARGET_TEMP: u16 = 1300;
+pub const MAX_TARGET_TEMP: u16 = 4300;
+
 #[derive(Clone, Debug, PartialEq, Eq, Default)]
 pub struct FrozenTarget {
     pub enabled: bool,
@@ -42,6 +54,25 @@ pub struct TemperatureUpdate {
     pub count: u8,
 }
 
+/// DS18B20-style temperature sensor on Frozen's one-wire bus
+#[derive(Debug, PartialEq, Clone)]
+pub struct OneWireSensor {
+    /// ROM code, family `0x28` first
+    pub id: [u8; 8],
+    /// centidegrees celcius
+    pub temp: u16,
+}
+
+impl fmt::Display for OneWireSensor {
+    /// ROM code as hex, ex. `28FFC2E9A52156F3`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.id {
+            write!(f, "{b:02X}")?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct GetTemperature {
     /// centidegrees celcius
@@ -69,6 +100,8 @@ impl Packet for FrozenPacket {
             0x90 => packet::parse_jumping_to_firmware("Frozen/JumpingToFirmware", buf)
                 .map(FrozenPacket::JumpingToFirmware),
             0xC0 => Self::parse_target_update(buf),
+            0xD0 => Self::parse_sensors(buf),
+            0xD1 => Self::parse_calibration(buf),
             0xD2 => Self::parse_priming_started(buf),
             _ => Err(PacketError::Unexpected {
                 subsystem_name: "Frozen",
@@ -79,6 +112,34 @@ impl Packet for FrozenPacket {
 }
 
 impl FrozenPacket {
+    /// D0 00, then per sensor: 8 byte ROM code, temp_high, temp_low
+    fn parse_sensors(buf: BytesMut) -> Result<Self, PacketError> {
+        validate_packet_at_least("Frozen/Sensors", &buf, 2)?;
+
+        if buf[1] != 0 || !(buf.len() - 2).is_multiple_of(10) {
+            return Err(invalid_structure(
+                "Frozen/Sensors",
+                "expected 10 bytes per sensor".to_string(),
+                buf,
+            ));
+        }
+
+        Ok(FrozenPacket::Sensors(
+            buf[2..]
+                .chunks_exact(10)
+                .map(|c| OneWireSensor {
+                    id: c[..8].try_into().unwrap(),
+                    temp: u16::from_be_bytes([c[8], c[9]]),
+                })
+                .collect(),
+        ))
+    }
+
+    fn parse_calibration(buf: BytesMut) -> Result<Self, PacketError> {
+        validate_packet_size("Frozen/Calibration", &buf, 2)?;
+        Ok(FrozenPacket::Calibration(buf[1]))
+    }
+
     fn parse_priming_started(buf: BytesMut) -> Result<Self, PacketError> {
         validate_packet_size("Frozen/PrimingStarted", &buf, 2)?;
         if buf[1] != 0 {
@@ -288,6 +349,67 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sensors() {
+        // capture #1
+        let data = hex!(
+            "D0 00
+            28 FF C2 E9 A5 21 56 F3 07 FB
+            28 FF 3A CF 23 22 31 12 09 34
+            28 FF CE 0B 2C E2 23 56 0A 0F
+            28 FF 07 E5 2C E2 20 39 0A 0F"
+        );
+        let Ok(FrozenPacket::Sensors(sensors)) = FrozenPacket::parse(BytesMut::from(&data[..]))
+        else {
+            panic!("Wrong packet type");
+        };
+        assert_eq!(
+            sensors.iter().map(|s| s.temp).collect::<Vec<_>>(),
+            vec![2043, 2356, 2575, 2575]
+        );
+        assert_eq!(sensors[3].to_string(), "28FF07E52CE22039");
+
+        // capture #2
+        let data = hex!(
+            "D0 00
+            28 FF C2 E9 A5 21 56 F3 08 08
+            28 FF 3A CF 23 22 31 12 09 3A
+            28 FF CE 0B 2C E2 23 56 0A 15
+            28 FF 07 E5 2C E2 20 39 0A 15"
+        );
+        let Ok(FrozenPacket::Sensors(sensors)) = FrozenPacket::parse(BytesMut::from(&data[..]))
+        else {
+            panic!("Wrong packet type");
+        };
+        assert_eq!(sensors.len(), 4);
+        assert_eq!(
+            sensors[0],
+            OneWireSensor {
+                id: hex!("28 FF C2 E9 A5 21 56 F3"),
+                temp: 2056
+            }
+        );
+        assert_eq!(sensors[0].to_string(), "28FFC2E9A52156F3");
+        assert_eq!(sensors[1].temp, 2362);
+        assert_eq!(sensors[3].temp, 2581);
+
+        assert_eq!(
+            FrozenPacket::parse(BytesMut::from(&hex!("D0 00")[..])),
+            Ok(FrozenPacket::Sensors(vec![]))
+        );
+        assert!(FrozenPacket::parse(BytesMut::from(&hex!("D0 00 28 FF C2")[..])).is_err());
+        assert!(FrozenPacket::parse(BytesMut::from(&hex!("D0")[..])).is_err());
+    }
+
+    #[test]
+    fn test_calibration() {
+        assert_eq!(
+            FrozenPacket::parse(BytesMut::from(&hex!("D1 00")[..])),
+            Ok(FrozenPacket::Calibration(0))
+        );
+        assert!(FrozenPacket::parse(BytesMut::from(&hex!("D1")[..])).is_err());
+    }
+
     #[test]
     fn test_unexpected() {
         assert_eq!(
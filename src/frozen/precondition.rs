@@ -0,0 +1,152 @@
+use jiff::SignedDuration;
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    common::packet::BedSide,
+    config::{Precondition, SideConfig},
+    frozen::{packet::FrozenTarget, state::FrozenState},
+};
+
+/// water temperature samples closer together than this are too noisy
+const SAMPLE_INT: Duration = Duration::from_secs(60);
+/// centidegrees celcius, only learn while the water is far from its target (running at full power)
+const MIN_TARGET_ERROR: u16 = 200;
+/// weight of new samples in the learned rate
+const RATE_ALPHA: f32 = 0.2;
+
+/// Learns how fast one side's water heats & cools
+#[derive(Debug, Default)]
+struct RateEstimator {
+    /// centidegrees celcius per minute
+    heat_rate: Option<f32>,
+    cool_rate: Option<f32>,
+    /// last sample (time, water temp)
+    last: Option<(Instant, u16)>,
+    water_temp: Option<u16>,
+}
+
+impl RateEstimator {
+    fn update(&mut self, now: Instant, water_temp: u16, target: Option<&FrozenTarget>) {
+        self.water_temp = Some(water_temp);
+
+        let Some((last_time, last_temp)) = self.last else {
+            self.last = Some((now, water_temp));
+            return;
+        };
+
+        let dt = now.duration_since(last_time);
+        if dt < SAMPLE_INT {
+            return;
+        }
+        self.last = Some((now, water_temp));
+
+        let Some(target) = target.filter(|t| t.enabled) else {
+            return;
+        };
+        if target.temp.abs_diff(last_temp) < MIN_TARGET_ERROR {
+            return;
+        }
+
+        let rate = (water_temp as f32 - last_temp as f32) / (dt.as_secs_f32() / 60.0);
+        let learned = match target.temp > last_temp {
+            true if rate > 0.0 => &mut self.heat_rate,
+            false if rate < 0.0 => &mut self.cool_rate,
+            // not moving towards the target (just enabled etc.)
+            _ => return,
+        };
+
+        let rate = rate.abs();
+        *learned = Some(match *learned {
+            Some(prev) => RATE_ALPHA.mul_add(rate - prev, prev),
+            None => rate,
+        });
+    }
+
+    /// how long to reach `target` (centidegrees celcius)
+    fn time_to_reach(&self, target: u16) -> Option<SignedDuration> {
+        let water_temp = self.water_temp?;
+        let rate = match target > water_temp {
+            true => self.heat_rate?,
+            false => self.cool_rate?,
+        };
+        let mins = target.abs_diff(water_temp) as f32 / rate;
+        Some(SignedDuration::from_secs_f32(mins * 60.0))
+    }
+}
+
+/// Works out how long before the profile starts to begin driving towards its first temperature
+#[derive(Debug, Default)]
+pub struct Preconditioner {
+    left: RateEstimator,
+    right: RateEstimator,
+}
+
+impl Preconditioner {
+    fn get(&self, side: &BedSide) -> &RateEstimator {
+        match side {
+            BedSide::Left => &self.left,
+            BedSide::Right => &self.right,
+        }
+    }
+
+    /// learns from the latest water temperatures
+    pub fn update(&mut self, state: &FrozenState) {
+        let Some(temp) = &state.temp else {
+            return;
+        };
+        let now = Instant::now();
+        self.left
+            .update(now, temp.left_temp, state.left_target.as_ref());
+        self.right
+            .update(now, temp.right_temp, state.right_target.as_ref());
+    }
+
+    pub fn lead_time(&self, side: &BedSide, cfg: &SideConfig) -> SignedDuration {
+        match cfg.precondition {
+            None => SignedDuration::ZERO,
+            Some(Precondition::Fixed(mins)) => SignedDuration::from_mins(mins as i64),
+            Some(Precondition::Learned(max_mins)) => {
+                let max = SignedDuration::from_mins(max_mins as i64);
+                cfg.first_point()
+                    // NOTE: also converts celcius -> centideg celcius
+                    .and_then(|(_, temp)| self.get(side).time_to_reach((temp * 100.0) as u16))
+                    // not learned yet
+                    .map_or(max, |lead| lead.min(max))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_learn_rate() {
+        let target = FrozenTarget {
+            enabled: true,
+            temp: 2000,
+        };
+        let mut e = RateEstimator::default();
+        let mut now = Instant::now();
+        assert_eq!(e.time_to_reach(2000), None);
+
+        // cools 50 per minute
+        for temp in [3000, 2950, 2900, 2850] {
+            e.update(now, temp, Some(&target));
+            now += SAMPLE_INT;
+        }
+        assert_eq!(e.cool_rate, Some(50.0));
+        assert_eq!(e.heat_rate, None);
+        assert_eq!(e.time_to_reach(2350), Some(SignedDuration::from_mins(10)));
+        // heating not learned
+        assert_eq!(e.time_to_reach(3000), None);
+
+        // near the target, not running at full power
+        let mut e = RateEstimator::default();
+        e.update(now, 2100, Some(&target));
+        now += SAMPLE_INT;
+        e.update(now, 2090, Some(&target));
+        assert_eq!(e.cool_rate, None);
+    }
+}
@@ -0,0 +1,419 @@
+use jiff::{SignedDuration, civil::Time};
+use rumqttc::AsyncClient;
+use strum_macros::Display;
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    frozen::{FrozenCommand, FrozenPacket},
+    mqtt::{ERROR, SUCCESS, publish_guaranteed_wait, publish_high_freq, publish_result},
+};
+
+/// how long Frozen has to acknowledge a `Prime`
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+/// how long without any priming message before giving up
+const STALL_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// doubled after every failed attempt
+const RETRY_BACKOFF: Duration = Duration::from_secs(2 * 60);
+const MAX_ATTEMPTS: u32 = 3;
+/// how close to `prime` time to start priming
+const PRIME_WINDOW: SignedDuration = SignedDuration::from_secs(30);
+
+pub const TOPIC_PRIME_NOW: &str = "opensleep/actions/prime_now";
+const TOPIC_ACTIVE: &str = "opensleep/state/frozen/priming/active";
+const TOPIC_PHASE: &str = "opensleep/state/frozen/priming/phase";
+const TOPIC_PROGRESS: &str = "opensleep/state/frozen/priming/progress";
+const TOPIC_OUTCOME: &str = "opensleep/state/frozen/priming/outcome";
+const TOPIC_ATTEMPT: &str = "opensleep/state/frozen/priming/attempt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum PrimingPhase {
+    #[strum(serialize = "idle")]
+    Idle,
+    /// `Prime` sent, waiting for Frozen to start
+    #[strum(serialize = "starting")]
+    Starting,
+    #[strum(serialize = "started")]
+    Started,
+    #[strum(serialize = "empty")]
+    Empty,
+    #[strum(serialize = "purge")]
+    Purge,
+    #[strum(serialize = "purge.fast")]
+    PurgeFast,
+    #[strum(serialize = "purge.side")]
+    PurgeSide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum PrimingOutcome {
+    Success,
+    /// `done because empty`
+    TankEmpty,
+    /// never acknowledged `Prime`
+    NoResponse,
+    /// stopped reporting progress
+    Stalled,
+}
+
+/// Priming runs, their `FW: [priming] ..` phases & retries
+#[derive(Debug)]
+pub struct PrimingController {
+    phase: PrimingPhase,
+    /// attempts of the current run, 0 when idle
+    attempt: u32,
+    /// when to send the next `Prime`
+    pending: Option<Instant>,
+    /// last phase change or priming message
+    last_progress: Instant,
+    last_scheduled: Instant,
+    progress: Option<String>,
+    outcome: Option<PrimingOutcome>,
+    /// blocks priming, the pumps would run dry
+    tank_out: bool,
+    /// tank was reinserted, prime once the bed is free
+    refilled: bool,
+    /// needs publishing
+    dirty: bool,
+}
+
+impl Default for PrimingController {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            phase: PrimingPhase::Idle,
+            attempt: 0,
+            pending: None,
+            last_progress: now,
+            // can prime right away
+            last_scheduled: now - Duration::from_secs(60),
+            progress: None,
+            outcome: None,
+            tank_out: false,
+            refilled: false,
+            dirty: true,
+        }
+    }
+}
+
+impl PrimingController {
+    pub fn is_active(&self) -> bool {
+        self.phase != PrimingPhase::Idle
+    }
+
+    /// starts a new run unless one is already running or waiting to retry
+    pub fn request(&mut self, now: Instant) {
+        if self.is_active() || self.pending.is_some() {
+            return;
+        }
+        if self.tank_out {
+            log::warn!("Not priming, the water tank is out");
+            return;
+        }
+        log::info!("Priming requested");
+        self.attempt = 0;
+        self.pending = Some(now);
+    }
+
+    /// `prime_now` action, refused while the bed is in use
+    pub async fn prime_now(&mut self, client: &mut AsyncClient, in_profile: bool, occupied: bool) {
+        let (status, msg) = match self.check_prime_now(in_profile, occupied) {
+            Ok(()) => {
+                self.request(Instant::now());
+                (SUCCESS, "priming started".to_string())
+            }
+            Err(reason) => {
+                log::warn!("Refusing to prime now: {reason}");
+                (ERROR, format!("not priming: {reason}"))
+            }
+        };
+        publish_result(client, "prime_now", status, msg).await;
+    }
+
+    /// blocks priming while the tank is out, primes once it is back
+    pub fn set_tank_out(&mut self, tank_out: bool) {
+        if tank_out == self.tank_out {
+            return;
+        }
+        self.tank_out = tank_out;
+        if tank_out {
+            // including retries, the reinsertion primes anyway
+            self.pending = None;
+            self.refilled = false;
+        } else {
+            log::info!("Water tank reinserted, priming once the bed is free");
+            self.refilled = true;
+        }
+    }
+
+    fn check_prime_now(&self, in_profile: bool, occupied: bool) -> Result<(), &'static str> {
+        if self.tank_out {
+            Err("the water tank is out")
+        } else if occupied {
+            Err("someone is in bed")
+        } else if in_profile {
+            Err("a profile is active")
+        } else if self.is_active() || self.pending.is_some() {
+            Err("already priming")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// requests priming at `prime` time or after a refill & checks for timeouts
+    pub fn update(
+        &mut self,
+        now: Instant,
+        local: Time,
+        prime: Time,
+        away_mode: bool,
+        in_profile: bool,
+        occupied: bool,
+    ) {
+        if self.refilled && self.check_prime_now(in_profile, occupied).is_ok() {
+            self.refilled = false;
+            self.request(now);
+        }
+
+        if !away_mode
+            && now.duration_since(self.last_scheduled) > Duration::from_secs(60)
+            && local.duration_until(prime).abs() < PRIME_WINDOW
+        {
+            self.last_scheduled = now;
+            self.request(now);
+        }
+
+        let elapsed = now.duration_since(self.last_progress);
+        match self.phase {
+            PrimingPhase::Idle => {}
+            PrimingPhase::Starting if elapsed > START_TIMEOUT => {
+                self.finish(now, PrimingOutcome::NoResponse);
+            }
+            _ if elapsed > STALL_TIMEOUT => self.finish(now, PrimingOutcome::Stalled),
+            _ => {}
+        }
+    }
+
+    /// `Prime` when a run or retry is due
+    pub fn next_command(&mut self, now: Instant) -> Option<FrozenCommand> {
+        if self.pending.is_none_or(|at| now < at) {
+            return None;
+        }
+        self.pending = None;
+        self.attempt += 1;
+        log::info!("Priming attempt {}/{MAX_ATTEMPTS}", self.attempt);
+        self.set_phase(now, PrimingPhase::Starting);
+        Some(FrozenCommand::Prime)
+    }
+
+    pub fn handle_packet(&mut self, now: Instant, packet: &FrozenPacket) {
+        match packet {
+            FrozenPacket::PrimingStarted => self.set_phase(now, PrimingPhase::Started),
+            FrozenPacket::Message(msg) => {
+                if let Some(msg) = msg.strip_prefix("FW: [priming] ") {
+                    self.handle_message(now, msg);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_message(&mut self, now: Instant, msg: &str) {
+        self.progress = Some(msg.to_string());
+        self.last_progress = now;
+        self.dirty = true;
+
+        let phase = match msg {
+            "done" => return self.finish(now, PrimingOutcome::Success),
+            "done because empty" => return self.finish(now, PrimingOutcome::TankEmpty),
+            "start" => PrimingPhase::Started,
+            m if m.starts_with("purge.fast") || m.starts_with("purge_fast") => {
+                PrimingPhase::PurgeFast
+            }
+            m if m.starts_with("purge.side") => PrimingPhase::PurgeSide,
+            m if m.starts_with("purge.empty") || m.starts_with("purge phase complete") => {
+                PrimingPhase::Empty
+            }
+            m if m.starts_with("purge") => PrimingPhase::Purge,
+            m if m.starts_with("empty") => PrimingPhase::Empty,
+            // ex. failed purge counts
+            _ => return,
+        };
+        self.set_phase(now, phase);
+    }
+
+    fn set_phase(&mut self, now: Instant, phase: PrimingPhase) {
+        if phase == self.phase {
+            return;
+        }
+        if self.phase == PrimingPhase::Idle {
+            // started without us, ex. by the button on the hub
+            self.attempt = self.attempt.max(1);
+            self.pending = None;
+        }
+        log::info!("Priming phase: {} -> {phase}", self.phase);
+        self.phase = phase;
+        self.last_progress = now;
+        self.dirty = true;
+    }
+
+    /// retries with backoff unless it succeeded, ran out of water or ran out of attempts
+    fn finish(&mut self, now: Instant, outcome: PrimingOutcome) {
+        self.phase = PrimingPhase::Idle;
+        self.outcome = Some(outcome);
+        self.dirty = true;
+
+        if outcome == PrimingOutcome::Success {
+            log::info!("Priming done");
+            self.attempt = 0;
+        } else if outcome == PrimingOutcome::TankEmpty {
+            // retrying can't help, primes again once the tank is refilled
+            log::warn!("Priming stopped, the water tank is empty");
+            self.attempt = 0;
+        } else if self.attempt < MAX_ATTEMPTS {
+            let backoff = RETRY_BACKOFF * 2u32.pow(self.attempt.saturating_sub(1));
+            log::warn!(
+                "Priming failed ({outcome}), retrying in {} seconds",
+                backoff.as_secs()
+            );
+            self.pending = Some(now + backoff);
+        } else {
+            log::error!("Priming failed ({outcome}) after {MAX_ATTEMPTS} attempts, giving up");
+            self.attempt = 0;
+        }
+    }
+
+    /// publishes any changes
+    pub async fn publish(&mut self, client: &mut AsyncClient) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let active = self.is_active().to_string();
+        publish_guaranteed_wait(client, TOPIC_ACTIVE, true, active).await;
+        publish_guaranteed_wait(client, TOPIC_PHASE, true, self.phase.to_string()).await;
+        publish_guaranteed_wait(client, TOPIC_ATTEMPT, true, self.attempt.to_string()).await;
+        if let Some(outcome) = self.outcome {
+            publish_guaranteed_wait(client, TOPIC_OUTCOME, true, outcome.to_string()).await;
+        }
+        if let Some(progress) = self.progress.take() {
+            publish_high_freq(client, TOPIC_PROGRESS, progress);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(msg: &str) -> FrozenPacket {
+        FrozenPacket::Message(format!("FW: [priming] {msg}"))
+    }
+
+    #[test]
+    fn test_phases() {
+        let mut p = PrimingController::default();
+        let now = Instant::now();
+        assert!(p.next_command(now).is_none());
+
+        p.request(now);
+        assert!(matches!(p.next_command(now), Some(FrozenCommand::Prime)));
+        assert_eq!(p.phase, PrimingPhase::Starting);
+
+        for (msg, phase) in [
+            ("start", PrimingPhase::Started),
+            (
+                "empty phase (3 remaining; runtime 100 ms)",
+                PrimingPhase::Empty,
+            ),
+            ("purge phase (4 iterations remaining)", PrimingPhase::Purge),
+            ("purge.fast (500 ms)", PrimingPhase::PurgeFast),
+            ("purge.side (left: ok)", PrimingPhase::PurgeSide),
+            (
+                "1 consecutive failed purges; 2 total failed",
+                PrimingPhase::PurgeSide,
+            ),
+            (
+                "purge phase complete. now final empty stage",
+                PrimingPhase::Empty,
+            ),
+        ] {
+            p.handle_packet(now, &message(msg));
+            assert_eq!(p.phase, phase, "{msg}");
+        }
+
+        p.handle_packet(now, &message("done"));
+        assert!(!p.is_active());
+        assert_eq!(p.outcome, Some(PrimingOutcome::Success));
+        assert!(p.next_command(now + STALL_TIMEOUT).is_none());
+    }
+
+    #[test]
+    fn test_retry() {
+        let mut p = PrimingController::default();
+        let local = Time::constant(15, 0, 0, 0);
+        let mut now = Instant::now();
+
+        // away mode
+        p.update(now, local, local, true, false, false);
+        assert!(p.next_command(now).is_none());
+
+        p.update(now, local, local, false, false, false);
+        for attempt in 1..=MAX_ATTEMPTS {
+            assert!(p.next_command(now).is_some(), "attempt {attempt}");
+            assert_eq!(p.attempt, attempt);
+
+            now += START_TIMEOUT + Duration::from_secs(1);
+            p.update(now, local, Time::MIN, false, false, false);
+            assert_eq!(p.outcome, Some(PrimingOutcome::NoResponse));
+            // backing off
+            assert!(p.next_command(now).is_none());
+            now += RETRY_BACKOFF * 4;
+        }
+
+        // gave up
+        assert!(p.next_command(now).is_none());
+
+        p.request(now);
+        p.next_command(now);
+        p.handle_packet(now, &message("done because empty"));
+        assert_eq!(p.outcome, Some(PrimingOutcome::TankEmpty));
+        assert_eq!(p.pending, None);
+        assert_eq!(p.attempt, 0);
+    }
+
+    #[test]
+    fn test_prime_now() {
+        let mut p = PrimingController::default();
+        assert_eq!(p.check_prime_now(false, true), Err("someone is in bed"));
+        assert_eq!(p.check_prime_now(true, false), Err("a profile is active"));
+        assert_eq!(p.check_prime_now(false, false), Ok(()));
+
+        p.request(Instant::now());
+        assert_eq!(p.check_prime_now(false, false), Err("already priming"));
+    }
+
+    #[test]
+    fn test_tank() {
+        let mut p = PrimingController::default();
+        let local = Time::constant(15, 0, 0, 0);
+        let now = Instant::now();
+
+        p.set_tank_out(true);
+        p.update(now, local, local, false, false, false);
+        assert!(p.next_command(now).is_none());
+        assert_eq!(
+            p.check_prime_now(false, false),
+            Err("the water tank is out")
+        );
+
+        // reinserted with someone in bed
+        p.set_tank_out(false);
+        p.update(now, local, Time::MIN, false, true, true);
+        assert!(p.next_command(now).is_none());
+
+        p.update(now, local, Time::MIN, false, false, false);
+        assert!(p.next_command(now).is_some());
+    }
+}
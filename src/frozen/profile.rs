@@ -2,8 +2,12 @@ use jiff::{SignedDuration, Timestamp, civil::Time, tz::TimeZone};
 
 use crate::{
     common::packet::BedSide,
-    config::{SideConfig, SidesConfig},
-    frozen::packet::FrozenTarget,
+    config::{Anchor, Curve, KeyframeTime, SideConfig, SidesConfig},
+    frozen::{
+        occupancy::{Occupancy, ProfileStart},
+        packet::FrozenTarget,
+        precondition::Preconditioner,
+    },
 };
 
 impl FrozenTarget {
@@ -12,34 +16,97 @@ impl FrozenTarget {
         away_mode: &bool,
         side_config: &SidesConfig,
         side: &BedSide,
+        precondition: &Preconditioner,
+        occupancy: &Occupancy,
     ) -> Self {
         if *away_mode {
             // disabled
             return FrozenTarget::default();
         }
 
-        let now = Timestamp::now().to_zoned(timezone.clone()).time();
+        let now = Timestamp::now().to_zoned(timezone.clone());
 
-        side_config.get_side(side).calc_target(now)
+        let cfg = side_config.get_side(side).for_now(&now);
+        let lead = precondition.lead_time(side, &cfg);
+        match occupancy.start(side, &cfg) {
+            ProfileStart::Scheduled => cfg.calc_target(now.time(), lead),
+            ProfileStart::Waiting => cfg.calc_waiting(now.time(), lead),
+            ProfileStart::Started(start) => SideConfig {
+                sleep: start,
+                ..cfg.into_owned()
+            }
+            .calc_target(now.time(), SignedDuration::ZERO),
+            ProfileStart::Off => FrozenTarget::default(),
+        }
     }
 }
 
 impl SideConfig {
-    fn calc_target(&self, now: Time) -> FrozenTarget {
-        if !self.temperatures.is_empty()
-            && let Some(t) = self.calc_progress(now)
-        {
-            return FrozenTarget {
+    /// `lead` is how long before the profile to start pre-conditioning
+    fn calc_target(&self, now: Time, lead: SignedDuration) -> FrozenTarget {
+        let temp = if !self.keyframes.is_empty() {
+            self.calc_keyframed(now)
+        } else if !self.temperatures.is_empty() {
+            // NOTE: also converts celcius -> centideg celcius
+            self.calc_progress(now).map(|t| self.lerp(t))
+        } else {
+            None
+        };
+
+        // pre-conditioning, drive towards the first temperature ahead of the profile
+        let temp = temp.or_else(|| {
+            let (start, first) = self.first_point()?;
+            (forward_duration(now, start) <= lead).then_some((first * 100.0) as u16)
+        });
+
+        match temp {
+            Some(temp) => FrozenTarget {
                 enabled: true,
-                // NOTE: also converts celcius -> centideg celcius
-                temp: self.lerp(t),
-            };
+                temp,
+            },
+            // disabled
+            None => FrozenTarget::default(),
+        }
+    }
+
+    /// While waiting for the side to be occupied, only pre-conditions
+    /// (from `lead` before the profile until the end of the occupancy window)
+    fn calc_waiting(&self, now: Time, lead: SignedDuration) -> FrozenTarget {
+        if let Some(occupancy) = &self.occupancy
+            && let Some((start, first)) = self.first_point()
+            && !lead.is_zero()
+        {
+            let window_end = self
+                .sleep
+                .wrapping_add(SignedDuration::from_mins(occupancy.after as i64));
+            let from = start.wrapping_sub(lead);
+            if forward_duration(from, now) <= forward_duration(from, window_end) {
+                return FrozenTarget {
+                    enabled: true,
+                    // NOTE: also converts celcius -> centideg celcius
+                    temp: (first * 100.0) as u16,
+                };
+            }
         }
 
         // disabled
         FrozenTarget::default()
     }
 
+    /// When the profile starts & its first temperature (degrees celcius)
+    pub(super) fn first_point(&self) -> Option<(Time, f32)> {
+        if self.keyframes.is_empty() {
+            return self.temperatures.first().map(|t| (self.sleep, *t));
+        }
+
+        let profile_duration = forward_duration(self.sleep, self.wake);
+        self.keyframes
+            .iter()
+            .map(|k| (k.at.since_sleep(profile_duration), k.temp))
+            .min_by_key(|(at, _)| *at)
+            .map(|(at, temp)| (self.sleep.wrapping_add(at), temp))
+    }
+
     /// Finds the current progress into the profile (0-1)
     /// Returns None if not in the profile
     fn calc_progress(&self, now: Time) -> Option<f32> {
@@ -52,6 +119,41 @@ impl SideConfig {
         }
     }
 
+    /// Interpolates between `keyframes`, which may start before `sleep` or end after `wake`
+    /// Returns centidegrees celcius, or None if outside of the keyframes
+    fn calc_keyframed(&self, now: Time) -> Option<u16> {
+        let profile_duration = forward_duration(self.sleep, self.wake);
+        let mut points: Vec<_> = self
+            .keyframes
+            .iter()
+            .map(|k| (k.at.since_sleep(profile_duration), k))
+            .collect();
+        points.sort_by_key(|(at, _)| *at);
+
+        let first = points.first()?.0;
+        let last = points.last()?.0;
+
+        // keyframes before `sleep` are the previous day
+        let rel = forward_duration(self.sleep, now);
+        let rel = [rel, rel - SignedDuration::from_hours(24)]
+            .into_iter()
+            .find(|rel| (first..=last).contains(rel))?;
+
+        // last keyframe at or before now
+        let i = points.partition_point(|(at, _)| *at <= rel) - 1;
+        let (start, from) = points[i];
+        let temp = match points.get(i + 1) {
+            Some((end, to)) if *end > start => {
+                let t = (rel - start).div_duration_f32(*end - start);
+                from.curve.interpolate(from.temp, to.temp, t)
+            }
+            _ => from.temp,
+        };
+
+        // NOTE: also converts celcius -> centideg celcius
+        Some((temp * 100.0) as u16)
+    }
+
     /// Linearly interpolates temperature profile with `t` from 0.-1.
     /// `profile` is in degrees celcius, return value is centidegrees celcius
     #[inline]
@@ -83,12 +185,34 @@ impl SideConfig {
     }
 }
 
+impl KeyframeTime {
+    /// offset from `sleep`, given the duration from `sleep` to `wake`
+    fn since_sleep(&self, profile_duration: SignedDuration) -> SignedDuration {
+        match self.anchor {
+            Anchor::Sleep => self.offset,
+            Anchor::Wake => profile_duration + self.offset,
+        }
+    }
+}
+
+impl Curve {
+    /// `t` from 0.-1.
+    fn interpolate(&self, from: f32, to: f32, t: f32) -> f32 {
+        let t = match self {
+            Curve::Step => 0.0,
+            Curve::Linear => t,
+            Curve::Smooth => t * t * (3.0 - 2.0 * t),
+        };
+        t.mul_add(to - from, from)
+    }
+}
+
 /// Finds the duration between two civil times, forward from A
 /// Ex:
 ///  1. a=18:00, b=6:00 -> 12 hours
 ///  2. a=16:00, b=6:00 -> 14 hours
 ///  3. a=4:00,  b=5:00 -> 1 hour
-fn forward_duration(a: Time, b: Time) -> SignedDuration {
+pub(super) fn forward_duration(a: Time, b: Time) -> SignedDuration {
     if b >= a {
         b.duration_until(a).abs()
     } else {
@@ -103,14 +227,22 @@ mod tests {
     use jiff::civil::time;
 
     use super::*;
+    use crate::config::{ControlMode, Keyframe};
 
     #[test]
     fn test_lerp() {
         let prof = SideConfig {
             temperatures: vec![0.0, 10.0, 20.0],
+            control: ControlMode::Water,
             sleep: time(18, 0, 0, 0),
             wake: time(6, 0, 0, 0),
             alarm: None,
+            keyframes: vec![],
+            precondition: None,
+            occupancy: None,
+            schedules: vec![],
+            preset: None,
+            limits: None,
         };
 
         assert_eq!(prof.lerp(0.0), 0);
@@ -124,9 +256,16 @@ mod tests {
     fn test_calc_profile_progress() {
         let prof = SideConfig {
             temperatures: vec![],
+            control: ControlMode::Water,
             sleep: time(18, 0, 0, 0),
             wake: time(6, 0, 0, 0),
             alarm: None,
+            keyframes: vec![],
+            precondition: None,
+            occupancy: None,
+            schedules: vec![],
+            preset: None,
+            limits: None,
         };
 
         assert_eq!(prof.calc_progress(time(17, 0, 0, 0)), None);
@@ -166,4 +305,83 @@ mod tests {
             SignedDuration::from_hours(1)
         );
     }
+
+    #[test]
+    fn test_keyframed() {
+        let kf = |at: &str, temp, curve| Keyframe {
+            at: at.parse().unwrap(),
+            temp,
+            curve,
+        };
+        let prof = SideConfig {
+            temperatures: vec![],
+            control: ControlMode::Water,
+            sleep: time(23, 0, 0, 0),
+            wake: time(7, 0, 0, 0),
+            alarm: None,
+            keyframes: vec![
+                kf("wake-1h", 19.0, Curve::Smooth),
+                kf("sleep-30m", 22.0, Curve::Linear),
+                kf("sleep+1h", 19.0, Curve::Step),
+                kf("wake", 27.0, Curve::Step),
+            ],
+            precondition: None,
+            occupancy: None,
+            schedules: vec![],
+            preset: None,
+            limits: None,
+        };
+
+        assert_eq!(prof.calc_keyframed(time(22, 0, 0, 0)), None);
+        assert_eq!(prof.calc_keyframed(time(22, 30, 0, 0)), Some(2200));
+        assert_eq!(prof.calc_keyframed(time(23, 0, 0, 0)), Some(2100));
+        assert_eq!(prof.calc_keyframed(time(3, 0, 0, 0)), Some(1900));
+        assert_eq!(prof.calc_keyframed(time(6, 30, 0, 0)), Some(2300));
+        assert_eq!(prof.calc_keyframed(time(7, 0, 0, 0)), Some(2700));
+        assert_eq!(prof.calc_keyframed(time(7, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_keyframe_time() {
+        for s in ["sleep", "sleep-30m", "sleep+1h", "wake-1h 20m"] {
+            assert_eq!(s.parse::<KeyframeTime>().unwrap().to_string(), s);
+        }
+        assert_eq!(
+            "wake - 20m".parse::<KeyframeTime>(),
+            Ok(KeyframeTime {
+                anchor: Anchor::Wake,
+                offset: SignedDuration::from_mins(-20),
+            })
+        );
+        assert!("bed+1h".parse::<KeyframeTime>().is_err());
+    }
+
+    #[test]
+    fn test_precondition() {
+        let prof = SideConfig {
+            temperatures: vec![20.0, 30.0],
+            control: ControlMode::Water,
+            sleep: time(22, 0, 0, 0),
+            wake: time(6, 0, 0, 0),
+            alarm: None,
+            keyframes: vec![],
+            precondition: None,
+            occupancy: None,
+            schedules: vec![],
+            preset: None,
+            limits: None,
+        };
+        let lead = SignedDuration::from_mins(45);
+
+        assert!(!prof.calc_target(time(21, 0, 0, 0), lead).enabled);
+        assert_eq!(prof.calc_target(time(21, 30, 0, 0), lead).temp, 2000);
+        assert!(prof.calc_target(time(21, 30, 0, 0), lead).enabled);
+        assert!(
+            !prof
+                .calc_target(time(21, 30, 0, 0), SignedDuration::ZERO)
+                .enabled
+        );
+        // after wake is the next night's lead
+        assert!(!prof.calc_target(time(6, 30, 0, 0), lead).enabled);
+    }
 }
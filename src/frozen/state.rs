@@ -1,11 +1,16 @@
 use rumqttc::AsyncClient;
+use strum_macros::Display;
 
 use crate::{
     common::{
         packet::{BedSide, HardwareInfo},
         serial::DeviceMode,
     },
-    frozen::packet::{FrozenPacket, FrozenTarget, TemperatureUpdate},
+    config::TemperatureUnit,
+    frozen::{
+        packet::{FrozenPacket, FrozenTarget, OneWireSensor, TemperatureUpdate},
+        telemetry::Telemetry,
+    },
     mqtt::{publish_guaranteed_wait, publish_high_freq},
 };
 
@@ -16,11 +21,45 @@ pub struct FrozenState {
     pub left_target: Option<FrozenTarget>,
     pub right_target: Option<FrozenTarget>,
     pub hardware_info: Option<HardwareInfo>,
+    pub sensors: Option<Vec<OneWireSensor>>,
+    /// `Calibration` response, messages follow it
+    pub calibration: Option<u8>,
+    /// set from `PrimingController`
     pub is_priming: bool,
+    pub water_tank: WaterTank,
+    /// of published temperatures
+    pub unit: TemperatureUnit,
+}
+
+/// Reed switch on the water tank
+#[derive(Clone, Copy, Debug, Default, PartialEq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum WaterTank {
+    /// until Frozen reports a change
+    #[default]
+    Unknown,
+    Full,
+    /// removed or needs a refill
+    Empty,
+}
+
+impl WaterTank {
+    /// `msg` is a full `FrozenPacket::Message`
+    fn from_message(msg: &str) -> Option<Self> {
+        match msg {
+            "FW: water empty -> full" => Some(Self::Full),
+            "FW: water full -> empty" => Some(Self::Empty),
+            _ => None,
+        }
+    }
 }
 
 const TOPIC_MODE: &str = "opensleep/state/frozen/mode";
 const TOPIC_HWINFO: &str = "opensleep/state/frozen/hwinfo";
+const TOPIC_SENSORS: &str = "opensleep/state/frozen/sensors";
+const TOPIC_FLASH: &str = "opensleep/state/frozen/calibration/flash";
+const TOPIC_CAL_INFO: &str = "opensleep/state/frozen/calibration/cal_info";
+const TOPIC_WATER_TANK: &str = "opensleep/state/frozen/water_tank";
 const TOPIC_LEFT_TEMP: &str = "opensleep/state/frozen/left_temp";
 const TOPIC_RIGHT_TEMP: &str = "opensleep/state/frozen/right_temp";
 const TOPIC_HEATSINK_TEMP: &str = "opensleep/state/frozen/heatsink_temp";
@@ -49,6 +88,8 @@ impl FrozenState {
 
     pub async fn publish_reset(&self, client: &mut AsyncClient) {
         publish_guaranteed_wait(client, TOPIC_MODE, false, DeviceMode::Unknown.to_string()).await;
+        let tank = WaterTank::Unknown.to_string();
+        publish_guaranteed_wait(client, TOPIC_WATER_TANK, true, tank).await;
     }
 
     pub async fn handle_packet(&mut self, client: &mut AsyncClient, packet: FrozenPacket) {
@@ -66,9 +107,14 @@ impl FrozenState {
                     u.error
                 );
 
-                publish_high_freq(client, TOPIC_LEFT_TEMP, u.left_temp.to_string());
-                publish_high_freq(client, TOPIC_RIGHT_TEMP, u.right_temp.to_string());
-                publish_high_freq(client, TOPIC_HEATSINK_TEMP, u.heatsink_temp.to_string());
+                let unit = self.unit;
+                publish_high_freq(client, TOPIC_LEFT_TEMP, unit.format_reading(u.left_temp));
+                publish_high_freq(client, TOPIC_RIGHT_TEMP, unit.format_reading(u.right_temp));
+                publish_high_freq(
+                    client,
+                    TOPIC_HEATSINK_TEMP,
+                    unit.format_reading(u.heatsink_temp),
+                );
 
                 self.temp = Some(u);
             }
@@ -80,7 +126,7 @@ impl FrozenState {
                     u.temp
                 );
                 let payload = match u.enabled {
-                    true => &u.temp.to_string(),
+                    true => &self.unit.format_reading(u.temp),
                     false => "disabled",
                 };
                 let topic = match side {
@@ -100,15 +146,32 @@ impl FrozenState {
                 publish_guaranteed_wait(client, TOPIC_HWINFO, true, info.to_string()).await;
                 self.hardware_info = Some(info);
             }
+            FrozenPacket::Sensors(sensors) => {
+                log::info!("One-wire sensors: {sensors:?}");
+                let payload = sensors
+                    .iter()
+                    .map(|s| format!("{s}={}", self.unit.format_reading(s.temp)))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                publish_guaranteed_wait(client, TOPIC_SENSORS, true, payload).await;
+                self.sensors = Some(sensors);
+            }
+            FrozenPacket::Calibration(value) => {
+                log::info!("Calibration status: {value}");
+                self.calibration = Some(value);
+            }
             FrozenPacket::JumpingToFirmware(code) => {
                 log::debug!("Jumping to firmware with code: 0x{code:02X}");
                 self.set_device_mode(client, DeviceMode::Firmware).await;
             }
             FrozenPacket::Message(msg) => {
-                if msg == "FW: water empty -> full" {
-                    log::warn!("Water tank reinserted");
-                } else if msg == "FW: water full -> empty" {
-                    log::warn!("Water tank removed");
+                if let Some(tank) = WaterTank::from_message(&msg) {
+                    match tank {
+                        WaterTank::Empty => log::warn!("Water tank removed"),
+                        _ => log::warn!("Water tank reinserted"),
+                    }
+                    publish_guaranteed_wait(client, TOPIC_WATER_TANK, true, tank.to_string()).await;
+                    self.water_tank = tank;
                 } else if let Some(stripped) = msg.strip_prefix("FW: [priming] ") {
                     // done because empty
                     // done
@@ -126,12 +189,17 @@ impl FrozenState {
                     // purge.side (%s: %s)
                     // purge.empty, both pumps at 12v
                     log::info!("Priming Message: {stripped}");
-
-                    match stripped {
-                        "done" | "done because empty" => self.is_priming = false,
-                        "start" => self.is_priming = true,
-                        _ => {}
-                    }
+                } else if let Some(status) = msg.strip_prefix("FW: flash ") {
+                    // ex. `locked`
+                    log::info!("Flash {status}");
+                    publish_guaranteed_wait(client, TOPIC_FLASH, true, status).await;
+                } else if let Some(status) = msg.strip_prefix("FW: cal_info ") {
+                    // ex. `valid`
+                    log::info!("Calibration info {status}");
+                    publish_guaranteed_wait(client, TOPIC_CAL_INFO, true, status).await;
+                } else if let Some(telemetry) = Telemetry::parse(&msg) {
+                    log::debug!("Telemetry: {telemetry:?}");
+                    telemetry.publish(client, self.unit);
                 } else {
                     log::debug!("Message: {msg}")
                 }
@@ -143,3 +211,22 @@ impl FrozenState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_water_tank() {
+        assert_eq!(
+            WaterTank::from_message("FW: water full -> empty"),
+            Some(WaterTank::Empty)
+        );
+        assert_eq!(
+            WaterTank::from_message("FW: water empty -> full"),
+            Some(WaterTank::Full)
+        );
+        assert_eq!(WaterTank::from_message("FW: [priming] done"), None);
+        assert_eq!(WaterTank::Empty.to_string(), "empty");
+    }
+}
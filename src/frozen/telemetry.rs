@@ -0,0 +1,168 @@
+//! Periodic PID & pump messages from Frozen's firmware, ex:
+//! `FW: pid[left] 25.812500 0.090498 -0.003750 0.094248 0.000000`
+//! `FW: pump[right] slow @ 6.044009V 0.161510A`
+
+use rumqttc::AsyncClient;
+use serde::Serialize;
+use strum_macros::{Display, EnumString};
+
+use crate::{common::packet::BedSide, config::TemperatureUnit, mqtt::publish_high_freq};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum PidLoop {
+    Left,
+    Right,
+    Heatsink,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PidTelemetry {
+    /// degrees celcius for `Left` & `Right`, unknown for `Heatsink`
+    pub setpoint: f32,
+    /// sum of the terms
+    pub output: f32,
+    pub p: f32,
+    pub i: f32,
+    pub d: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PumpTelemetry {
+    /// ex `slow`
+    pub speed: String,
+    /// volts
+    pub voltage: f32,
+    /// amps
+    pub current: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Telemetry {
+    Pid(PidLoop, PidTelemetry),
+    Pump(BedSide, PumpTelemetry),
+}
+
+impl Telemetry {
+    /// `msg` is a full `FrozenPacket::Message`, `None` if it isn't telemetry
+    pub fn parse(msg: &str) -> Option<Self> {
+        let msg = msg.strip_prefix("FW: ")?;
+        let (kind, rest) = msg.split_once('[')?;
+        let (target, values) = rest.split_once(']')?;
+
+        match kind {
+            "pid" => {
+                let values = values
+                    .split_whitespace()
+                    .map(|v| v.parse().ok())
+                    .collect::<Option<Vec<f32>>>()?;
+                let [setpoint, output, p, i, d] = values[..] else {
+                    return None;
+                };
+                Some(Telemetry::Pid(
+                    target.parse().ok()?,
+                    PidTelemetry {
+                        setpoint,
+                        output,
+                        p,
+                        i,
+                        d,
+                    },
+                ))
+            }
+            "pump" => {
+                let side = match target {
+                    "left" => BedSide::Left,
+                    "right" => BedSide::Right,
+                    _ => return None,
+                };
+                let (speed, electrical) = values.split_once('@')?;
+                let (voltage, current) = electrical.trim().split_once(' ')?;
+                Some(Telemetry::Pump(
+                    side,
+                    PumpTelemetry {
+                        speed: speed.trim().to_string(),
+                        voltage: voltage.strip_suffix('V')?.parse().ok()?,
+                        current: current.trim().strip_suffix('A')?.parse().ok()?,
+                    },
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// publishes as JSON to `pid/LOOP` or `pump/SIDE`,
+    /// water setpoints in `unit`
+    pub fn publish(&self, client: &mut AsyncClient, unit: TemperatureUnit) {
+        let (topic, json) = match self {
+            Telemetry::Pid(pid_loop, pid) => {
+                let mut pid = pid.clone();
+                if *pid_loop != PidLoop::Heatsink {
+                    pid.setpoint = unit.convert_celsius(pid.setpoint);
+                }
+                (
+                    format!("opensleep/state/frozen/pid/{pid_loop}"),
+                    serde_json::to_string(&pid),
+                )
+            }
+            Telemetry::Pump(side, pump) => (
+                match side {
+                    BedSide::Left => "opensleep/state/frozen/pump/left".to_string(),
+                    BedSide::Right => "opensleep/state/frozen/pump/right".to_string(),
+                },
+                serde_json::to_string(pump),
+            ),
+        };
+
+        match json {
+            Ok(json) => publish_high_freq(client, topic, json),
+            Err(e) => log::error!("Failed to serialize telemetry: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pid() {
+        assert_eq!(
+            Telemetry::parse("FW: pid[left] 25.812500 0.090498 -0.003750 0.094248 0.000000"),
+            Some(Telemetry::Pid(
+                PidLoop::Left,
+                PidTelemetry {
+                    setpoint: 25.8125,
+                    output: 0.090498,
+                    p: -0.00375,
+                    i: 0.094248,
+                    d: 0.0,
+                }
+            ))
+        );
+        assert!(matches!(
+            Telemetry::parse("FW: pid[heatsink] 3.062500 0.693750 0.693750 0.000000 0.000000"),
+            Some(Telemetry::Pid(PidLoop::Heatsink, _))
+        ));
+        assert_eq!(Telemetry::parse("FW: pid[left] 25.812500 0.090498"), None);
+        assert_eq!(Telemetry::parse("FW: pid[middle] 1 2 3 4 5"), None);
+    }
+
+    #[test]
+    fn test_parse_pump() {
+        assert_eq!(
+            Telemetry::parse("FW: pump[right] slow @ 6.044009V 0.161510A"),
+            Some(Telemetry::Pump(
+                BedSide::Right,
+                PumpTelemetry {
+                    speed: "slow".to_string(),
+                    voltage: 6.044009,
+                    current: 0.16151,
+                }
+            ))
+        );
+        assert_eq!(Telemetry::parse("FW: pump[left] slow"), None);
+        assert_eq!(Telemetry::parse("FW: [priming] start"), None);
+        assert_eq!(Telemetry::parse("FW: water empty -> full"), None);
+    }
+}
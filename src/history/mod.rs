@@ -0,0 +1,151 @@
+//! On-device history, one CSV file per night with a record every minute
+
+mod record;
+
+use std::path::{Path, PathBuf};
+
+use jiff::{Span, Timestamp, civil::Date, tz::TimeZone};
+use rumqttc::AsyncClient;
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    bus::Snapshot,
+    common::packet::BedSide,
+    config::TemperatureUnit,
+    frozen::packet::FrozenTarget,
+    mqtt::{ERROR, SUCCESS, publish_guaranteed_wait, publish_result},
+    sensor::biometrics::{BiometricsManager, current_night},
+};
+use record::{Record, night_path};
+
+/// relative to the working directory
+const HISTORY_DIR: &str = "history";
+/// nights of history to keep
+const RETENTION_NIGHTS: i64 = 30;
+const RECORD_INT: Duration = Duration::from_secs(60);
+
+pub const TOPIC_EXPORT_HISTORY: &str = "opensleep/actions/export_history";
+const TOPIC_EXPORT: &str = "opensleep/state/history/export";
+
+pub struct HistoryRecorder {
+    timezone: TimeZone,
+    dir: PathBuf,
+    snapshot_rx: watch::Receiver<Snapshot>,
+    last_record: Instant,
+    /// last night written to, used to apply retention once per night
+    night: Option<Date>,
+}
+
+impl HistoryRecorder {
+    pub fn new(timezone: TimeZone, snapshot_rx: watch::Receiver<Snapshot>) -> Self {
+        Self {
+            timezone,
+            dir: PathBuf::from(HISTORY_DIR),
+            snapshot_rx,
+            last_record: Instant::now(),
+            night: None,
+        }
+    }
+
+    pub fn set_timezone(&mut self, timezone: TimeZone) {
+        self.timezone = timezone;
+    }
+
+    /// appends a record every `RECORD_INT`
+    pub fn update(&mut self, biometrics: &BiometricsManager) {
+        let now = Instant::now();
+        if now.duration_since(self.last_record) < RECORD_INT {
+            return;
+        }
+        self.last_record = now;
+
+        let night = current_night(&self.timezone);
+        let new_night = self.night != Some(night);
+        self.night = Some(night);
+
+        // off the Sensor task, which has to keep up with the piezo stream
+        let record = self.make_record(biometrics);
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking(move || {
+            if new_night {
+                apply_retention(&dir, night);
+            }
+            if let Err(e) = record::append(&dir, night, &record) {
+                log::error!("Failed to write history: {e}");
+            }
+        });
+    }
+
+    fn make_record(&self, biometrics: &BiometricsManager) -> Record {
+        let snapshot = self.snapshot_rx.borrow();
+        let presence = snapshot.presence.clone().unwrap_or_default();
+        let water = snapshot.water_temp.as_ref();
+        let target = |t: &Option<FrozenTarget>| t.as_ref().filter(|t| t.enabled).map(|t| t.temp);
+        let bed = snapshot.bed_temp.as_ref();
+        let left = biometrics.latest(&BedSide::Left);
+        let right = biometrics.latest(&BedSide::Right);
+
+        Record {
+            time: Some(Timestamp::now()),
+            presence_left: presence.left,
+            presence_right: presence.right,
+            bed_temp_left: bed.map(|t| t.bed_side(&BedSide::Left)),
+            bed_temp_right: bed.map(|t| t.bed_side(&BedSide::Right)),
+            ambient_temp: bed.map(|t| t.ambient),
+            water_temp_left: water.map(|t| t.left_temp),
+            water_temp_right: water.map(|t| t.right_temp),
+            target_left: target(&snapshot.left_target),
+            target_right: target(&snapshot.right_target),
+            heart_rate_left: left.heart_rate,
+            heart_rate_right: right.heart_rate,
+            breathing_rate_left: left.breathing_rate,
+            breathing_rate_right: right.breathing_rate,
+            hrv_rmssd_left: left.hrv_rmssd,
+            hrv_rmssd_right: right.hrv_rmssd,
+            restlessness_left: left.restlessness,
+            restlessness_right: right.restlessness,
+        }
+    }
+
+    /// publishes a night's CSV to `TOPIC_EXPORT` in a new task,
+    /// temperatures in `unit`
+    pub fn export(&self, client: &AsyncClient, night: Date, unit: TemperatureUnit) {
+        let mut client = client.clone();
+        let path = night_path(&self.dir, night);
+        tokio::spawn(async move {
+            let (status, msg) = match export(&mut client, &path, unit).await {
+                Ok(lines) => (SUCCESS, format!("exported {lines} records from {night}")),
+                Err(e) => {
+                    log::error!("Failed to export history for {night}: {e}");
+                    (ERROR, format!("no history for {night}: {e}"))
+                }
+            };
+            publish_result(&mut client, "export_history", status, msg).await;
+        });
+    }
+}
+
+/// blocking, removes nights older than `RETENTION_NIGHTS`
+fn apply_retention(dir: &Path, night: Date) {
+    let oldest = night.saturating_sub(Span::new().days(RETENTION_NIGHTS));
+    match record::remove_before(dir, oldest) {
+        Ok(0) => {}
+        Ok(n) => log::info!("Removed {n} nights of history before {oldest}"),
+        // doesn't exist before the first record
+        Err(record::HistoryError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::error!("Failed to apply history retention: {e}"),
+    }
+}
+
+async fn export(
+    client: &mut AsyncClient,
+    path: &Path,
+    unit: TemperatureUnit,
+) -> Result<usize, record::HistoryError> {
+    let csv = tokio::fs::read_to_string(path).await?;
+    let csv = record::convert_units(&csv, unit)?;
+    let lines = csv.lines().count().saturating_sub(1);
+    publish_guaranteed_wait(client, TOPIC_EXPORT, false, csv).await;
+    Ok(lines)
+}
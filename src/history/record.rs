@@ -0,0 +1,199 @@
+use std::{
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+use jiff::{Timestamp, civil::Date};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::TemperatureUnit;
+
+/// `Record` columns in centidegrees celcius
+const TEMP_COLUMNS: [&str; 7] = [
+    "bed_temp_left",
+    "bed_temp_right",
+    "ambient_temp",
+    "water_temp_left",
+    "water_temp_right",
+    "target_left",
+    "target_right",
+];
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("I/O: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("CSV: {0}")]
+    Csv(#[from] csv::Error),
+}
+
+/// One minute of history. Missing values are left empty.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Record {
+    pub time: Option<Timestamp>,
+    pub presence_left: bool,
+    pub presence_right: bool,
+    /// centidegrees celcius, average of the side's bed sensors
+    pub bed_temp_left: Option<u16>,
+    pub bed_temp_right: Option<u16>,
+    /// centidegrees celcius
+    pub ambient_temp: Option<u16>,
+    /// centidegrees celcius
+    pub water_temp_left: Option<u16>,
+    pub water_temp_right: Option<u16>,
+    /// centidegrees celcius, empty when disabled
+    pub target_left: Option<u16>,
+    pub target_right: Option<u16>,
+    /// BPM
+    pub heart_rate_left: Option<f32>,
+    pub heart_rate_right: Option<f32>,
+    /// breaths per minute
+    pub breathing_rate_left: Option<f32>,
+    pub breathing_rate_right: Option<f32>,
+    /// ms
+    pub hrv_rmssd_left: Option<f32>,
+    pub hrv_rmssd_right: Option<f32>,
+    /// % of the last minute spent moving
+    pub restlessness_left: Option<f32>,
+    pub restlessness_right: Option<f32>,
+}
+
+/// `DIR/YYYY-MM-DD.csv`
+pub fn night_path(dir: &Path, night: Date) -> PathBuf {
+    dir.join(format!("{night}.csv"))
+}
+
+/// appends a record to the night's file, writing the header if it's new
+pub fn append(dir: &Path, night: Date, record: &Record) -> Result<(), HistoryError> {
+    fs::create_dir_all(dir)?;
+    let path = night_path(dir, night);
+    let is_new = !path.exists();
+
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(is_new)
+        .from_writer(file);
+    writer.serialize(record)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// converts a night's CSV temperatures to `temp_reading`s in `unit`
+pub fn convert_units(csv: &str, unit: TemperatureUnit) -> Result<String, HistoryError> {
+    if unit == TemperatureUnit::Celsius {
+        return Ok(csv.to_string());
+    }
+
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let headers = reader.headers()?.clone();
+    let temp_columns: Vec<bool> = headers.iter().map(|h| TEMP_COLUMNS.contains(&h)).collect();
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(&headers)?;
+    for row in reader.records() {
+        let row = row?;
+        let converted =
+            row.iter()
+                .zip(&temp_columns)
+                .map(|(value, is_temp)| match value.parse::<u16>() {
+                    Ok(temp) if *is_temp => unit.format_reading(temp),
+                    _ => value.to_string(),
+                });
+        writer.write_record(converted)?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// deletes every night before `oldest`
+/// returns the number of files removed
+pub fn remove_before(dir: &Path, oldest: Date) -> Result<usize, HistoryError> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "csv") {
+            continue;
+        }
+
+        let night = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<Date>().ok());
+
+        if night.is_some_and(|n| n < oldest) {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::civil::date;
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("opensleep-history-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_append() {
+        let dir = test_dir("append");
+        let night = date(2025, 1, 1);
+        let a = Record {
+            time: Some(Timestamp::from_second(1_700_000_000).unwrap()),
+            presence_left: true,
+            bed_temp_left: Some(3100),
+            target_left: Some(2700),
+            heart_rate_left: Some(58.5),
+            ..Default::default()
+        };
+        let b = Record {
+            time: Some(Timestamp::from_second(1_700_000_060).unwrap()),
+            ..Default::default()
+        };
+
+        append(&dir, night, &a).unwrap();
+        append(&dir, night, &b).unwrap();
+
+        let mut reader = csv::Reader::from_path(night_path(&dir, night)).unwrap();
+        let records: Vec<Record> = reader.deserialize().map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![a, b]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_convert_units() {
+        let csv = "time,presence_left,bed_temp_left,target_left,heart_rate_left\n\
+                   2023-11-14T22:13:20Z,true,3100,,58.5\n";
+        assert_eq!(
+            convert_units(csv, TemperatureUnit::Fahrenheit).unwrap(),
+            "time,presence_left,bed_temp_left,target_left,heart_rate_left\n\
+             2023-11-14T22:13:20Z,true,87.8,,58.5\n"
+        );
+        assert_eq!(convert_units(csv, TemperatureUnit::Celsius).unwrap(), csv);
+    }
+
+    #[test]
+    fn test_remove_before() {
+        let dir = test_dir("retention");
+        for day in 1..=5 {
+            append(&dir, date(2025, 1, day), &Record::default()).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "keep me").unwrap();
+
+        assert_eq!(remove_before(&dir, date(2025, 1, 4)).unwrap(), 3);
+        assert!(!night_path(&dir, date(2025, 1, 3)).exists());
+        assert!(night_path(&dir, date(2025, 1, 4)).exists());
+        assert!(dir.join("notes.txt").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
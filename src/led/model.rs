@@ -85,6 +85,7 @@ pub enum PatternNext {
 
 #[derive(Clone)]
 #[repr(u8)]
+#[allow(clippy::enum_variant_names)]
 pub enum Gamma {
     /// gamma = 2.4
     Gamma2_4 = 0b00,
@@ -1,17 +1,20 @@
+mod bus;
 mod common;
 mod config;
 mod frozen;
+mod history;
 mod led;
 mod mqtt;
 mod reset;
 mod sensor;
+mod sleep;
 
 use std::fs;
 
 use config::Config;
 use tokio::sync::{mpsc, watch};
 
-use crate::{led::IS31FL3194Controller, mqtt::MqttManager, reset::ResetController};
+use crate::{bus::Bus, led::IS31FL3194Controller, mqtt::MqttManager, reset::ResetController};
 
 pub const VERSION: &str = "2.0.0";
 pub const NAME: &str = "opensleep";
@@ -39,12 +42,15 @@ pub async fn main() {
     resetter.reset_subsystems().await.unwrap();
     let led = IS31FL3194Controller::new(resetter.take());
 
-    let (calibrate_tx, calibrate_rx) = mpsc::channel(32);
+    let (sensor_tx, sensor_rx) = mpsc::channel(32);
+    let (frozen_tx, frozen_rx) = mpsc::channel(32);
+    let bus = Bus::default();
 
     let mut mqtt_man = MqttManager::new(
         config_tx.clone(),
         config_rx.clone(),
-        calibrate_tx,
+        sensor_tx,
+        frozen_tx,
         device_label,
     );
 
@@ -57,6 +63,8 @@ pub async fn main() {
         res = frozen::run(
             frozen::PORT,
             config_rx.clone(),
+            bus.clone(),
+            frozen_rx,
             led,
             mqtt_man.client.clone()
         ) => {
@@ -70,7 +78,8 @@ pub async fn main() {
             sensor::PORT,
             config_tx,
             config_rx,
-            calibrate_rx,
+            sensor_rx,
+            bus,
             mqtt_man.client.clone()
         ) => {
             match res {
@@ -2,10 +2,21 @@ use crate::{
     NAME, VERSION,
     config::{
         self, Config,
-        mqtt::{TOPIC_SET_AWAY_MODE, TOPIC_SET_PRESENCE, TOPIC_SET_PRIME, TOPIC_SET_PROFILE},
+        mqtt::{
+            TOPIC_SET_AWAY_MODE, TOPIC_SET_PRESENCE, TOPIC_SET_PRESET, TOPIC_SET_PRIME,
+            TOPIC_SET_PROFILE,
+        },
     },
-    sensor::presence::TOPIC_CALIBRATE,
+    frozen::{
+        FrozenAction,
+        fault::TOPIC_CLEAR_FAULT,
+        manual::{OverrideRequest, TOPIC_SET_OVERRIDE},
+        priming::TOPIC_PRIME_NOW,
+    },
+    history::TOPIC_EXPORT_HISTORY,
+    sensor::{SensorAction, presence::TOPIC_CALIBRATE, recorder::TOPIC_RECORD_RAW},
 };
+use jiff::civil::Date;
 use rumqttc::{
     AsyncClient, ConnectionError, Event, EventLoop, LastWill, MqttOptions, Packet, Publish, QoS,
 };
@@ -27,13 +38,14 @@ const TOPIC_RESULT_ACTION: &str = "opensleep/result/action";
 const TOPIC_RESULT_STATUS: &str = "opensleep/result/status";
 const TOPIC_RESULT_MSG: &str = "opensleep/result/message";
 
-const SUCCESS: &str = "success";
-const ERROR: &str = "error";
+pub const SUCCESS: &str = "success";
+pub const ERROR: &str = "error";
 
 pub struct MqttManager {
     config_tx: watch::Sender<Config>,
     config_rx: watch::Receiver<Config>,
-    calibrate_tx: mpsc::Sender<()>,
+    sensor_tx: mpsc::Sender<SensorAction>,
+    frozen_tx: mpsc::Sender<FrozenAction>,
     pub client: AsyncClient,
     eventloop: EventLoop,
     device_label: String,
@@ -44,7 +56,8 @@ impl MqttManager {
     pub fn new(
         config_tx: watch::Sender<Config>,
         config_rx: watch::Receiver<Config>,
-        calibrate_tx: mpsc::Sender<()>,
+        sensor_tx: mpsc::Sender<SensorAction>,
+        frozen_tx: mpsc::Sender<FrozenAction>,
         device_label: String,
     ) -> Self {
         log::info!("Initializing MQTT...");
@@ -61,6 +74,8 @@ impl MqttManager {
         let mut opts = MqttOptions::new("opensleep", &cfg.server, cfg.port);
         opts.set_keep_alive(Duration::from_secs(60));
         opts.set_credentials(&cfg.user, &cfg.password);
+        // history exports are a whole night of CSV
+        opts.set_max_packet_size(10 * 1024, 1024 * 1024);
         opts.set_last_will(LastWill {
             topic: TOPIC_AVAILABILITY.to_string(),
             message: OFFLINE.into(),
@@ -73,7 +88,8 @@ impl MqttManager {
         Self {
             config_tx,
             config_rx,
-            calibrate_tx,
+            sensor_tx,
+            frozen_tx,
             client,
             eventloop,
             device_label,
@@ -188,9 +204,15 @@ impl MqttManager {
         let device_label = self.device_label.clone();
         tokio::spawn(async move {
             subscribe(&mut client, TOPIC_CALIBRATE).await;
+            subscribe(&mut client, TOPIC_EXPORT_HISTORY).await;
+            subscribe(&mut client, TOPIC_RECORD_RAW).await;
             subscribe(&mut client, TOPIC_SET_AWAY_MODE).await;
             subscribe(&mut client, TOPIC_SET_PRIME).await;
             subscribe(&mut client, TOPIC_SET_PROFILE).await;
+            subscribe(&mut client, TOPIC_SET_PRESET).await;
+            subscribe(&mut client, TOPIC_SET_OVERRIDE).await;
+            subscribe(&mut client, TOPIC_CLEAR_FAULT).await;
+            subscribe(&mut client, TOPIC_PRIME_NOW).await;
             subscribe(&mut client, TOPIC_SET_PRESENCE).await;
 
             config.publish(&mut client).await;
@@ -206,8 +228,8 @@ impl MqttManager {
     /// MUST exit quickly without calling any MQTT commands (unless in another task)
     async fn handle_action(&mut self, publ: Publish) {
         if publ.topic == TOPIC_CALIBRATE {
-            let (status, msg) = if let Err(e) = self.calibrate_tx.try_send(()) {
-                let msg = format!("Failed to send to calibrate channel: {e}");
+            let (status, msg) = if let Err(e) = self.sensor_tx.try_send(SensorAction::Calibrate) {
+                let msg = format!("Failed to send to sensor channel: {e}");
                 log::error!("{msg}");
                 (ERROR, msg)
             } else {
@@ -217,6 +239,83 @@ impl MqttManager {
             tokio::spawn(async move {
                 publish_result(&mut client, "calibrate", status, msg).await;
             });
+        } else if publ.topic == TOPIC_EXPORT_HISTORY {
+            let payload = String::from_utf8_lossy(&publ.payload);
+            let res = payload
+                .trim()
+                .parse::<Date>()
+                .map_err(|e| format!("invalid night `{payload}`: {e}"))
+                .and_then(|night| {
+                    self.sensor_tx
+                        .try_send(SensorAction::ExportHistory(night))
+                        .map_err(|e| format!("Failed to send to sensor channel: {e}"))
+                });
+
+            // result is published by the exporter on success
+            if let Err(msg) = res {
+                log::error!("{msg}");
+                let mut client = self.client.clone();
+                tokio::spawn(async move {
+                    publish_result(&mut client, "export_history", ERROR, msg).await;
+                });
+            }
+        } else if publ.topic == TOPIC_RECORD_RAW {
+            let payload = String::from_utf8_lossy(&publ.payload);
+            let res = payload
+                .trim()
+                .parse::<bool>()
+                .map_err(|e| format!("invalid value `{payload}`: {e}"))
+                .and_then(|enabled| {
+                    self.sensor_tx
+                        .try_send(SensorAction::RecordRaw(enabled))
+                        .map_err(|e| format!("Failed to send to sensor channel: {e}"))
+                });
+
+            // result is published by the recorder on success
+            if let Err(msg) = res {
+                log::error!("{msg}");
+                let mut client = self.client.clone();
+                tokio::spawn(async move {
+                    publish_result(&mut client, "record_raw", ERROR, msg).await;
+                });
+            }
+        } else if publ.topic == TOPIC_SET_OVERRIDE {
+            let payload = String::from_utf8_lossy(&publ.payload);
+            let unit = self.config_rx.borrow().unit;
+            let res = OverrideRequest::parse(&payload, unit).and_then(|req| {
+                self.frozen_tx
+                    .try_send(FrozenAction::Override(req))
+                    .map_err(|e| format!("Failed to send to frozen channel: {e}"))
+            });
+
+            // result is published by Frozen on success
+            if let Err(msg) = res {
+                log::error!("{msg}");
+                let mut client = self.client.clone();
+                tokio::spawn(async move {
+                    publish_result(&mut client, "set_override", ERROR, msg).await;
+                });
+            }
+        } else if publ.topic == TOPIC_CLEAR_FAULT {
+            // result is published by Frozen on success
+            if let Err(e) = self.frozen_tx.try_send(FrozenAction::ClearFault) {
+                let msg = format!("Failed to send to frozen channel: {e}");
+                log::error!("{msg}");
+                let mut client = self.client.clone();
+                tokio::spawn(async move {
+                    publish_result(&mut client, "clear_fault", ERROR, msg).await;
+                });
+            }
+        } else if publ.topic == TOPIC_PRIME_NOW {
+            // result is published by Frozen
+            if let Err(e) = self.frozen_tx.try_send(FrozenAction::PrimeNow) {
+                let msg = format!("Failed to send to frozen channel: {e}");
+                log::error!("{msg}");
+                let mut client = self.client.clone();
+                tokio::spawn(async move {
+                    publish_result(&mut client, "prime_now", ERROR, msg).await;
+                });
+            }
         } else if publ.topic.starts_with("opensleep/actions/set_") {
             self.handle_set_action(publ).await;
         } else {
@@ -268,7 +367,7 @@ impl MqttManager {
     }
 }
 
-async fn publish_result(client: &mut AsyncClient, action: &str, status: &str, msg: String) {
+pub async fn publish_result(client: &mut AsyncClient, action: &str, status: &str, msg: String) {
     publish_guaranteed_wait(client, TOPIC_RESULT_ACTION, false, action).await;
     publish_guaranteed_wait(client, TOPIC_RESULT_STATUS, false, status).await;
     publish_guaranteed_wait(client, TOPIC_RESULT_MSG, false, msg).await;
@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+
+use super::{
+    SAMPLE_RATE,
+    filter::{BandPass, Decimator},
+    spectrum::{Peak, Spectrum},
+};
+
+/// Hz, respiration is slow so decimate further
+const BREATHING_SAMPLE_RATE: f32 = 10.0;
+const DECIMATION: usize = SAMPLE_RATE as usize / BREATHING_SAMPLE_RATE as usize;
+/// 6-30 breaths per minute
+const BAND: (f32, f32) = (0.1, 0.5);
+const FILTER_BAND: (f32, f32) = (0.08, 0.7);
+/// 60 seconds
+const WINDOW_LEN: usize = 60 * BREATHING_SAMPLE_RATE as usize;
+/// re-estimate every 10 seconds
+const UPDATE_LEN: usize = 10 * BREATHING_SAMPLE_RATE as usize;
+const FFT_SIZE: usize = 4096;
+/// below this the estimate is flagged as poor signal
+const MIN_CONFIDENCE: f32 = 0.4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreathingRate {
+    /// breaths per minute
+    pub bpm: f32,
+    /// 0-1
+    pub confidence: f32,
+}
+
+impl BreathingRate {
+    /// signal quality flag
+    pub fn is_ok(&self) -> bool {
+        self.confidence >= MIN_CONFIDENCE
+    }
+}
+
+/// Breathing rate estimation from the low frequency part of a single piezo channel
+pub struct BreathingEstimator {
+    decimator: Decimator,
+    filter: BandPass,
+    window: VecDeque<f32>,
+    since_update: usize,
+    spectrum: Spectrum,
+    scratch: Vec<f32>,
+}
+
+impl BreathingEstimator {
+    pub fn new() -> Self {
+        Self {
+            decimator: Decimator::new(DECIMATION),
+            filter: BandPass::new(BREATHING_SAMPLE_RATE, FILTER_BAND.0, FILTER_BAND.1),
+            window: VecDeque::with_capacity(WINDOW_LEN),
+            since_update: 0,
+            spectrum: Spectrum::new(FFT_SIZE, BREATHING_SAMPLE_RATE),
+            scratch: Vec::with_capacity(WINDOW_LEN),
+        }
+    }
+
+    /// takes in a decimated sample (at `SAMPLE_RATE`)
+    /// returns a new estimate every `UPDATE_LEN` (decimated) samples once the window is full
+    pub fn push(&mut self, x: f32) -> Option<BreathingRate> {
+        let x = self.decimator.push(x)?;
+
+        if self.window.len() == WINDOW_LEN {
+            self.window.pop_front();
+        }
+        self.window.push_back(self.filter.process(x));
+        self.since_update += 1;
+
+        if self.window.len() < WINDOW_LEN || self.since_update < UPDATE_LEN {
+            return None;
+        }
+        self.since_update = 0;
+
+        self.scratch.clear();
+        self.scratch.extend(self.window.iter());
+        let Peak { freq, confidence } = self.spectrum.dominant(&self.scratch, BAND, 1)?;
+
+        Some(BreathingRate {
+            bpm: freq * 60.0,
+            confidence,
+        })
+    }
+
+    pub fn reset(&mut self) {
+        self.decimator.reset();
+        self.filter.reset();
+        self.window.clear();
+        self.since_update = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn test_breathing_rate() {
+        let mut est = BreathingEstimator::new();
+        let mut last = None;
+        // 90 seconds @ 15 breaths/min with a 72 BPM heartbeat on top
+        for i in 0..(90 * SAMPLE_RATE as usize) {
+            let t = i as f32 / SAMPLE_RATE;
+            let x =
+                32768.0 + 300.0 * (2.0 * PI * 0.25 * t).sin() + 50.0 * (2.0 * PI * 1.2 * t).sin();
+            if let Some(br) = est.push(x) {
+                last = Some(br);
+            }
+        }
+
+        let br = last.expect("no estimate produced");
+        assert!((br.bpm - 15.0).abs() < 1.0, "got {}", br.bpm);
+        assert!(br.is_ok(), "got {}", br.confidence);
+    }
+
+    #[test]
+    fn test_breathing_quality() {
+        assert!(
+            BreathingRate {
+                bpm: 12.0,
+                confidence: 0.9
+            }
+            .is_ok()
+        );
+        assert!(
+            !BreathingRate {
+                bpm: 12.0,
+                confidence: 0.1
+            }
+            .is_ok()
+        );
+    }
+}
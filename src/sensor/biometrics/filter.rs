@@ -0,0 +1,204 @@
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+/// Second order IIR filter (Direct Form I)
+/// Coefficients from the RBJ Audio EQ Cookbook, Butterworth Q
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+    primed: bool,
+}
+
+impl Biquad {
+    pub fn low_pass(sample_rate: f32, cutoff: f32) -> Self {
+        let (cos, alpha) = Self::params(sample_rate, cutoff);
+        Self::normalized(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn high_pass(sample_rate: f32, cutoff: f32) -> Self {
+        let (cos, alpha) = Self::params(sample_rate, cutoff);
+        Self::normalized(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    /// returns (cos(w0), alpha)
+    fn params(sample_rate: f32, cutoff: f32) -> (f32, f32) {
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        (w0.cos(), w0.sin() / (2.0 * FRAC_1_SQRT_2))
+    }
+
+    fn normalized(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+            primed: false,
+        }
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        // start in steady state for the first sample, otherwise the
+        // piezo's large DC offset rings through the whole window
+        if !self.primed {
+            let dc_gain = (self.b0 + self.b1 + self.b2) / (1.0 + self.a1 + self.a2);
+            self.x1 = x;
+            self.x2 = x;
+            self.y1 = x * dc_gain;
+            self.y2 = x * dc_gain;
+            self.primed = true;
+        }
+
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+
+    pub fn reset(&mut self) {
+        self.x1 = 0.0;
+        self.x2 = 0.0;
+        self.y1 = 0.0;
+        self.y2 = 0.0;
+        self.primed = false;
+    }
+}
+
+/// High-pass followed by a low-pass
+#[derive(Debug, Clone)]
+pub struct BandPass {
+    high: Biquad,
+    low: Biquad,
+}
+
+impl BandPass {
+    pub fn new(sample_rate: f32, low_cutoff: f32, high_cutoff: f32) -> Self {
+        Self {
+            high: Biquad::high_pass(sample_rate, low_cutoff),
+            low: Biquad::low_pass(sample_rate, high_cutoff),
+        }
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        self.low.process(self.high.process(x))
+    }
+
+    pub fn reset(&mut self) {
+        self.high.reset();
+        self.low.reset();
+    }
+}
+
+/// Averages every `factor` samples into one
+#[derive(Debug, Clone)]
+pub struct Decimator {
+    factor: usize,
+    sum: f32,
+    count: usize,
+}
+
+impl Decimator {
+    pub fn new(factor: usize) -> Self {
+        assert!(factor > 0, "Decimator factor must be non-zero!");
+        Self {
+            factor,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, x: f32) -> Option<f32> {
+        self.sum += x;
+        self.count += 1;
+        if self.count < self.factor {
+            return None;
+        }
+        let out = self.sum / self.factor as f32;
+        self.sum = 0.0;
+        self.count = 0;
+        Some(out)
+    }
+
+    pub fn reset(&mut self) {
+        self.sum = 0.0;
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, sample_rate: f32, n: usize) -> impl Iterator<Item = f32> {
+        (0..n).map(move |i| (2.0 * PI * freq * i as f32 / sample_rate).sin())
+    }
+
+    fn peak(samples: impl Iterator<Item = f32>) -> f32 {
+        samples.fold(0.0, |acc, x| acc.max(x.abs()))
+    }
+
+    #[test]
+    fn test_decimator() {
+        let mut dec = Decimator::new(4);
+        assert_eq!(dec.push(1.0), None);
+        assert_eq!(dec.push(2.0), None);
+        assert_eq!(dec.push(3.0), None);
+        assert_eq!(dec.push(6.0), Some(3.0));
+        assert_eq!(dec.push(1.0), None);
+    }
+
+    #[test]
+    fn test_high_pass_removes_dc() {
+        let mut hp = Biquad::high_pass(100.0, 0.5);
+        let out: Vec<f32> = (0..1000).map(|_| hp.process(32768.0)).collect();
+        assert!(out.iter().all(|y| y.abs() < 1.0));
+    }
+
+    #[test]
+    fn test_band_pass() {
+        let mut bp = BandPass::new(100.0, 0.7, 3.0);
+        // in band
+        let passed = peak(sine(1.5, 100.0, 2000).map(|x| bp.process(x)).skip(500));
+        assert!(passed > 0.8, "in band attenuated to {passed}");
+
+        // above band
+        bp.reset();
+        let stopped = peak(sine(20.0, 100.0, 2000).map(|x| bp.process(x)).skip(500));
+        assert!(stopped < 0.1, "out of band passed {stopped}");
+
+        // below band
+        bp.reset();
+        let stopped = peak(sine(0.05, 100.0, 4000).map(|x| bp.process(x)).skip(500));
+        assert!(stopped < 0.1, "out of band passed {stopped}");
+    }
+}
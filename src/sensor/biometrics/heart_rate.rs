@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+
+use super::{
+    SAMPLE_RATE,
+    filter::BandPass,
+    spectrum::{Peak, Spectrum},
+};
+
+/// 45-180 BPM
+const BAND: (f32, f32) = (0.75, 3.0);
+/// BCG energy sits mostly in harmonics of the heart rate, so let those through
+const FILTER_BAND: (f32, f32) = (0.75, 8.0);
+const HARMONICS: usize = 2;
+/// 20 seconds
+const WINDOW_LEN: usize = 20 * SAMPLE_RATE as usize;
+/// re-estimate every 5 seconds
+const UPDATE_LEN: usize = 5 * SAMPLE_RATE as usize;
+const FFT_SIZE: usize = 8192;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartRate {
+    pub bpm: f32,
+    /// 0-1
+    pub confidence: f32,
+}
+
+/// Ballistocardiography heart rate estimation from a single piezo channel
+pub struct HeartRateEstimator {
+    filter: BandPass,
+    window: VecDeque<f32>,
+    since_update: usize,
+    spectrum: Spectrum,
+    scratch: Vec<f32>,
+}
+
+impl HeartRateEstimator {
+    pub fn new() -> Self {
+        Self {
+            filter: BandPass::new(SAMPLE_RATE, FILTER_BAND.0, FILTER_BAND.1),
+            window: VecDeque::with_capacity(WINDOW_LEN),
+            since_update: 0,
+            spectrum: Spectrum::new(FFT_SIZE, SAMPLE_RATE),
+            scratch: Vec::with_capacity(WINDOW_LEN),
+        }
+    }
+
+    /// takes in a decimated sample (at `SAMPLE_RATE`)
+    /// returns a new estimate every `UPDATE_LEN` samples once the window is full
+    pub fn push(&mut self, x: f32) -> Option<HeartRate> {
+        if self.window.len() == WINDOW_LEN {
+            self.window.pop_front();
+        }
+        self.window.push_back(self.filter.process(x));
+        self.since_update += 1;
+
+        if self.window.len() < WINDOW_LEN || self.since_update < UPDATE_LEN {
+            return None;
+        }
+        self.since_update = 0;
+
+        self.scratch.clear();
+        self.scratch.extend(self.window.iter());
+        let Peak { freq, confidence } = self.spectrum.dominant(&self.scratch, BAND, HARMONICS)?;
+
+        Some(HeartRate {
+            bpm: freq * 60.0,
+            confidence,
+        })
+    }
+
+    pub fn reset(&mut self) {
+        self.filter.reset();
+        self.window.clear();
+        self.since_update = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn test_heart_rate() {
+        let mut est = HeartRateEstimator::new();
+        let mut last = None;
+        // 30 seconds @ 72 BPM riding on a large DC offset
+        for i in 0..(30 * SAMPLE_RATE as usize) {
+            let t = i as f32 / SAMPLE_RATE;
+            let x = 32768.0 + 200.0 * (2.0 * PI * 1.2 * t).sin();
+            if let Some(hr) = est.push(x) {
+                last = Some(hr);
+            }
+        }
+
+        let hr = last.expect("no estimate produced");
+        assert!((hr.bpm - 72.0).abs() < 2.0, "got {}", hr.bpm);
+        assert!(hr.confidence > 0.5, "got {}", hr.confidence);
+    }
+
+    #[test]
+    fn test_heart_rate_needs_full_window() {
+        let mut est = HeartRateEstimator::new();
+        for _ in 0..(WINDOW_LEN - 1) {
+            assert_eq!(est.push(32768.0), None);
+        }
+
+        est.reset();
+        assert!(est.window.is_empty());
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+
+use jiff::civil::Date;
+
+use super::{
+    SAMPLE_RATE,
+    filter::{BandPass, Biquad},
+};
+
+/// isolates the sharp (J-wave) part of each heartbeat
+const FILTER_BAND: (f32, f32) = (1.0, 10.0);
+/// smooths the squared signal into one hump per beat
+const ENVELOPE_CUTOFF: f32 = 4.0;
+/// a beat must reach this fraction of the recent maximum
+const THRESHOLD_RATIO: f32 = 0.3;
+/// 3 seconds
+const THRESHOLD_LEN: usize = 3 * SAMPLE_RATE as usize;
+/// physiological inter-beat interval limits (ms)
+const MIN_IBI: f32 = 300.0;
+const MAX_IBI: f32 = 2000.0;
+/// intervals further than this from the recent median are artifacts
+const MAX_DEVIATION: f32 = 0.3;
+const MEDIAN_LEN: usize = 8;
+/// seconds, rolling window for RMSSD & SDNN
+const WINDOW_SECS: f64 = 5. * 60.;
+/// re-compute every minute
+const UPDATE_LEN: u64 = 60 * SAMPLE_RATE as u64;
+const MIN_INTERVALS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hrv {
+    /// ms
+    pub rmssd: f32,
+    /// ms
+    pub sdnn: f32,
+}
+
+struct Interval {
+    /// seconds since reset
+    time: f64,
+    /// ms
+    ibi: f32,
+    /// whether the previous interval in the window is the directly preceding beat
+    successive: bool,
+}
+
+/// Beat-to-beat interval detection & rolling heart rate variability metrics
+pub struct HrvEstimator {
+    filter: BandPass,
+    envelope: Biquad,
+    recent: VecDeque<f32>,
+    /// last 2 envelope values
+    prev: (f32, f32),
+    samples: u64,
+    last_beat: Option<u64>,
+    /// used to set the refractory period between beats
+    heart_rate: Option<f32>,
+    intervals: VecDeque<Interval>,
+    chain_broken: bool,
+}
+
+impl HrvEstimator {
+    pub fn new() -> Self {
+        Self {
+            filter: BandPass::new(SAMPLE_RATE, FILTER_BAND.0, FILTER_BAND.1),
+            envelope: Biquad::low_pass(SAMPLE_RATE, ENVELOPE_CUTOFF),
+            recent: VecDeque::with_capacity(THRESHOLD_LEN),
+            prev: (0.0, 0.0),
+            samples: 0,
+            last_beat: None,
+            heart_rate: None,
+            intervals: VecDeque::new(),
+            chain_broken: true,
+        }
+    }
+
+    /// latest heart rate estimate in BPM
+    pub fn set_heart_rate(&mut self, bpm: f32) {
+        self.heart_rate = Some(bpm);
+    }
+
+    /// takes in a decimated sample (at `SAMPLE_RATE`)
+    /// returns new metrics every `UPDATE_LEN` samples once `WINDOW_SECS` of beats are collected
+    pub fn push(&mut self, x: f32) -> Option<Hrv> {
+        let filtered = self.filter.process(x);
+        let env = self.envelope.process(filtered * filtered);
+
+        if self.recent.len() == THRESHOLD_LEN {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(env);
+
+        // local maximum at the previous sample
+        let (prev2, prev1) = self.prev;
+        if prev1 > prev2 && prev1 >= env && self.recent.len() == THRESHOLD_LEN {
+            let max = self.recent.iter().fold(0.0f32, |a, &b| a.max(b));
+            if prev1 > max * THRESHOLD_RATIO {
+                self.on_peak(self.samples - 1);
+            }
+        }
+        self.prev = (prev1, env);
+        self.samples += 1;
+
+        // drop old intervals
+        let now = self.samples as f64 / SAMPLE_RATE as f64;
+        while self
+            .intervals
+            .front()
+            .is_some_and(|i| now - i.time > WINDOW_SECS)
+        {
+            self.intervals.pop_front();
+            if let Some(first) = self.intervals.front_mut() {
+                first.successive = false;
+            }
+        }
+
+        if now < WINDOW_SECS
+            || !self.samples.is_multiple_of(UPDATE_LEN)
+            || self.intervals.len() < MIN_INTERVALS
+        {
+            return None;
+        }
+
+        Some(self.calc())
+    }
+
+    fn on_peak(&mut self, sample: u64) {
+        let Some(last) = self.last_beat else {
+            self.last_beat = Some(sample);
+            return;
+        };
+
+        let ibi = (sample - last) as f32 * 1000.0 / SAMPLE_RATE;
+        let expected = self.expected_ibi();
+
+        // refractory period, likely a secondary wave of the same beat
+        if ibi < expected.unwrap_or(MIN_IBI / 0.6) * 0.6 || ibi < MIN_IBI {
+            return;
+        }
+
+        self.last_beat = Some(sample);
+
+        let valid =
+            ibi <= MAX_IBI && expected.is_none_or(|e| ((ibi - e) / e).abs() <= MAX_DEVIATION);
+
+        if !valid {
+            // missed or extra beat, next interval can't be compared to the last one
+            self.chain_broken = true;
+            return;
+        }
+
+        self.intervals.push_back(Interval {
+            time: sample as f64 / SAMPLE_RATE as f64,
+            ibi,
+            successive: !self.chain_broken,
+        });
+        self.chain_broken = false;
+    }
+
+    /// median of recent intervals, falling back on the heart rate estimate
+    fn expected_ibi(&self) -> Option<f32> {
+        if self.intervals.len() >= 3 {
+            let mut recent: Vec<f32> = self
+                .intervals
+                .iter()
+                .rev()
+                .take(MEDIAN_LEN)
+                .map(|i| i.ibi)
+                .collect();
+            recent.sort_by(f32::total_cmp);
+            return Some(recent[recent.len() / 2]);
+        }
+
+        self.heart_rate.map(|bpm| 60_000.0 / bpm)
+    }
+
+    fn calc(&self) -> Hrv {
+        let n = self.intervals.len() as f32;
+        let mean = self.intervals.iter().map(|i| i.ibi).sum::<f32>() / n;
+        let var = self
+            .intervals
+            .iter()
+            .map(|i| (i.ibi - mean).powi(2))
+            .sum::<f32>()
+            / n;
+
+        let (sum_sq, count) = self
+            .intervals
+            .iter()
+            .zip(self.intervals.iter().skip(1))
+            .filter(|(_, b)| b.successive)
+            .fold((0.0, 0), |(sum, count), (a, b)| {
+                (sum + (b.ibi - a.ibi).powi(2), count + 1)
+            });
+
+        Hrv {
+            rmssd: if count > 0 {
+                (sum_sq / count as f32).sqrt()
+            } else {
+                0.0
+            },
+            sdnn: var.sqrt(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.filter.reset();
+        self.envelope.reset();
+        self.recent.clear();
+        self.prev = (0.0, 0.0);
+        self.samples = 0;
+        self.last_beat = None;
+        self.heart_rate = None;
+        self.intervals.clear();
+        self.chain_broken = true;
+    }
+}
+
+/// Running average of HRV metrics over a night
+#[derive(Default)]
+pub struct NightlyHrv {
+    night: Option<Date>,
+    rmssd_sum: f32,
+    sdnn_sum: f32,
+    count: u32,
+}
+
+impl NightlyHrv {
+    /// adds a measurement, starting over when `night` changes
+    /// returns the nightly average
+    pub fn add(&mut self, night: Date, hrv: &Hrv) -> Hrv {
+        if self.night != Some(night) {
+            *self = Self {
+                night: Some(night),
+                ..Default::default()
+            };
+        }
+
+        self.rmssd_sum += hrv.rmssd;
+        self.sdnn_sum += hrv.sdnn;
+        self.count += 1;
+
+        Hrv {
+            rmssd: self.rmssd_sum / self.count as f32,
+            sdnn: self.sdnn_sum / self.count as f32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::civil::date;
+
+    use super::*;
+
+    /// BCG-like pulse train with alternating intervals
+    fn pulses(intervals_ms: &[f32], secs: usize) -> Vec<f32> {
+        let n = secs * SAMPLE_RATE as usize;
+        let mut beats = vec![];
+        let mut t = 0.5;
+        let mut i = 0;
+        while t < secs as f32 {
+            beats.push(t);
+            t += intervals_ms[i % intervals_ms.len()] / 1000.0;
+            i += 1;
+        }
+
+        (0..n)
+            .map(|s| {
+                let t = s as f32 / SAMPLE_RATE;
+                let pulse: f32 = beats
+                    .iter()
+                    .map(|b| {
+                        let d = (t - b) / 0.03;
+                        (-d * d).exp()
+                    })
+                    .sum();
+                32768.0 + 500.0 * pulse
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hrv() {
+        let mut est = HrvEstimator::new();
+        let mut last = None;
+        for x in pulses(&[950.0, 1050.0], 6 * 60) {
+            if let Some(hrv) = est.push(x) {
+                last = Some(hrv);
+            }
+        }
+
+        let hrv = last.expect("no metrics produced");
+        assert!((hrv.rmssd - 100.0).abs() < 20.0, "rmssd {}", hrv.rmssd);
+        assert!((hrv.sdnn - 50.0).abs() < 15.0, "sdnn {}", hrv.sdnn);
+    }
+
+    #[test]
+    fn test_hrv_steady() {
+        let mut est = HrvEstimator::new();
+        let mut last = None;
+        for x in pulses(&[800.0], 6 * 60) {
+            if let Some(hrv) = est.push(x) {
+                last = Some(hrv);
+            }
+        }
+
+        let hrv = last.expect("no metrics produced");
+        assert!(hrv.rmssd < 15.0, "rmssd {}", hrv.rmssd);
+        assert!(hrv.sdnn < 15.0, "sdnn {}", hrv.sdnn);
+    }
+
+    #[test]
+    fn test_nightly() {
+        let mut nightly = NightlyHrv::default();
+        let a = Hrv {
+            rmssd: 40.0,
+            sdnn: 60.0,
+        };
+        let b = Hrv {
+            rmssd: 60.0,
+            sdnn: 80.0,
+        };
+
+        nightly.add(date(2025, 1, 1), &a);
+        assert_eq!(
+            nightly.add(date(2025, 1, 1), &b),
+            Hrv {
+                rmssd: 50.0,
+                sdnn: 70.0
+            }
+        );
+
+        // new night starts over
+        assert_eq!(nightly.add(date(2025, 1, 2), &a), a);
+    }
+}
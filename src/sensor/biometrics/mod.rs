@@ -0,0 +1,405 @@
+//! Per-side biometrics from the piezo (ballistocardiography) stream
+
+mod breathing;
+mod filter;
+mod heart_rate;
+mod hrv;
+mod movement;
+mod spectrum;
+
+use jiff::{Span, Timestamp, civil::Date, tz::TimeZone};
+use rumqttc::AsyncClient;
+
+use crate::{
+    common::packet::BedSide,
+    mqtt::publish_high_freq,
+    sensor::{
+        packet::{CapacitanceData, PiezoData},
+        presence::PresenceState,
+        state::PIEZO_FREQ,
+    },
+    sleep::stage::Epoch,
+};
+use breathing::BreathingEstimator;
+use filter::Decimator;
+use heart_rate::HeartRateEstimator;
+use hrv::{Hrv, HrvEstimator, NightlyHrv};
+use movement::{MovementTracker, NightlyTossTurns};
+
+/// Hz, piezo samples are decimated down to this before processing
+const SAMPLE_RATE: f32 = 100.0;
+const DECIMATION: usize = PIEZO_FREQ as usize / SAMPLE_RATE as usize;
+/// heart rate estimates below this are left out of sleep epochs
+const MIN_HR_CONFIDENCE: f32 = 0.3;
+
+struct SideTopics {
+    heart_rate: &'static str,
+    heart_rate_confidence: &'static str,
+    breathing_rate: &'static str,
+    breathing_ok: &'static str,
+    hrv_rmssd: &'static str,
+    hrv_sdnn: &'static str,
+    hrv_rmssd_nightly: &'static str,
+    hrv_sdnn_nightly: &'static str,
+    restlessness: &'static str,
+    toss_turns: &'static str,
+}
+
+const LEFT_TOPICS: SideTopics = SideTopics {
+    heart_rate: "opensleep/state/sensor/left/heart_rate",
+    heart_rate_confidence: "opensleep/state/sensor/left/heart_rate_confidence",
+    breathing_rate: "opensleep/state/sensor/left/breathing_rate",
+    breathing_ok: "opensleep/state/sensor/left/breathing_ok",
+    hrv_rmssd: "opensleep/state/sensor/left/hrv_rmssd",
+    hrv_sdnn: "opensleep/state/sensor/left/hrv_sdnn",
+    hrv_rmssd_nightly: "opensleep/state/sensor/left/hrv_rmssd_nightly",
+    hrv_sdnn_nightly: "opensleep/state/sensor/left/hrv_sdnn_nightly",
+    restlessness: "opensleep/state/sensor/left/restlessness",
+    toss_turns: "opensleep/state/sensor/left/toss_turns",
+};
+
+const RIGHT_TOPICS: SideTopics = SideTopics {
+    heart_rate: "opensleep/state/sensor/right/heart_rate",
+    heart_rate_confidence: "opensleep/state/sensor/right/heart_rate_confidence",
+    breathing_rate: "opensleep/state/sensor/right/breathing_rate",
+    breathing_ok: "opensleep/state/sensor/right/breathing_ok",
+    hrv_rmssd: "opensleep/state/sensor/right/hrv_rmssd",
+    hrv_sdnn: "opensleep/state/sensor/right/hrv_sdnn",
+    hrv_rmssd_nightly: "opensleep/state/sensor/right/hrv_rmssd_nightly",
+    hrv_sdnn_nightly: "opensleep/state/sensor/right/hrv_sdnn_nightly",
+    restlessness: "opensleep/state/sensor/right/restlessness",
+    toss_turns: "opensleep/state/sensor/right/toss_turns",
+};
+
+pub struct BiometricsManager {
+    client: AsyncClient,
+    timezone: TimeZone,
+    /// whether the piezo stream is complete enough to trust
+    reliable: bool,
+    left: SideBiometrics,
+    right: SideBiometrics,
+}
+
+struct SideBiometrics {
+    side: BedSide,
+    topics: SideTopics,
+    /// whether this side was occupied on the last update
+    occupied: bool,
+    decimator: Decimator,
+    heart_rate: HeartRateEstimator,
+    breathing: BreathingEstimator,
+    hrv: HrvEstimator,
+    /// kept across bed exits
+    nightly_hrv: NightlyHrv,
+    movement: MovementTracker,
+    /// kept across bed exits
+    nightly_toss_turns: NightlyTossTurns,
+    latest: LatestBiometrics,
+    epoch: EpochStats,
+}
+
+/// most recent estimates of a side, cleared when its occupancy changes
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LatestBiometrics {
+    /// BPM, only confident estimates
+    pub heart_rate: Option<f32>,
+    /// breaths per minute, only good quality estimates
+    pub breathing_rate: Option<f32>,
+    /// ms
+    pub hrv_rmssd: Option<f32>,
+    /// % of the last minute spent moving
+    pub restlessness: Option<f32>,
+}
+
+/// accumulates biometrics for the current sleep epoch
+#[derive(Default)]
+struct EpochStats {
+    vacated: bool,
+    moving_secs: u32,
+    hr_sum: f32,
+    hr_count: u32,
+    br_sum: f32,
+    br_count: u32,
+    br_poor: bool,
+}
+
+impl BiometricsManager {
+    pub fn new(client: AsyncClient, timezone: TimeZone) -> Self {
+        Self {
+            client,
+            timezone,
+            reliable: true,
+            left: SideBiometrics::new(BedSide::Left, LEFT_TOPICS),
+            right: SideBiometrics::new(BedSide::Right, RIGHT_TOPICS),
+        }
+    }
+
+    /// nights start & end in this timezone
+    pub fn set_timezone(&mut self, timezone: TimeZone) {
+        self.timezone = timezone;
+    }
+
+    /// unreliable heart rate, breathing & HRV estimates aren't published,
+    /// and are left out of sleep epochs & `latest`
+    pub fn set_reliable(&mut self, reliable: bool) {
+        self.reliable = reliable;
+    }
+
+    /// only processes sides that are occupied
+    pub fn update_piezo(&mut self, data: &PiezoData, presence: Option<&PresenceState>) {
+        // not configured yet
+        if data.freq != PIEZO_FREQ {
+            return;
+        }
+
+        let presence = presence.cloned().unwrap_or_default();
+        self.left.update(
+            &mut self.client,
+            &self.timezone,
+            &data.left_samples,
+            &presence,
+            self.reliable,
+        );
+        self.right.update(
+            &mut self.client,
+            &self.timezone,
+            &data.right_samples,
+            &presence,
+            self.reliable,
+        );
+    }
+
+    pub fn update_capacitance(&mut self, data: &CapacitanceData, presence: Option<&PresenceState>) {
+        let presence = presence.cloned().unwrap_or_default();
+        self.left.update_capacitance(&data.values[0..3], &presence);
+        self.right.update_capacitance(&data.values[3..6], &presence);
+    }
+
+    pub fn latest(&self, side: &BedSide) -> &LatestBiometrics {
+        match side {
+            BedSide::Left => &self.left.latest,
+            BedSide::Right => &self.right.latest,
+        }
+    }
+
+    /// summarizes and resets a side's biometrics since the last call
+    pub fn take_epoch(&mut self, side: &BedSide) -> Epoch {
+        match side {
+            BedSide::Left => self.left.take_epoch(),
+            BedSide::Right => self.right.take_epoch(),
+        }
+    }
+}
+
+impl SideBiometrics {
+    fn new(side: BedSide, topics: SideTopics) -> Self {
+        Self {
+            side,
+            topics,
+            occupied: false,
+            decimator: Decimator::new(DECIMATION),
+            heart_rate: HeartRateEstimator::new(),
+            breathing: BreathingEstimator::new(),
+            hrv: HrvEstimator::new(),
+            nightly_hrv: NightlyHrv::default(),
+            movement: MovementTracker::new(),
+            nightly_toss_turns: NightlyTossTurns::default(),
+            latest: LatestBiometrics::default(),
+            epoch: EpochStats::default(),
+        }
+    }
+
+    /// `values` are this side's 3 capacitance channels
+    fn update_capacitance(&mut self, values: &[u16], presence: &PresenceState) {
+        // resetting is left to the piezo stream
+        if presence.get_side(&self.side) && self.occupied {
+            self.movement.push_capacitance(values);
+        }
+    }
+
+    fn update(
+        &mut self,
+        client: &mut AsyncClient,
+        timezone: &TimeZone,
+        samples: &[u16],
+        presence: &PresenceState,
+        reliable: bool,
+    ) {
+        let occupied = presence.get_side(&self.side);
+        if occupied != self.occupied {
+            log::debug!("Biometrics[{}] occupied: {occupied}", self.side);
+            self.occupied = occupied;
+            self.reset();
+            if !occupied {
+                self.epoch.vacated = true;
+            }
+        }
+
+        if !occupied {
+            return;
+        }
+
+        for &sample in samples {
+            let Some(x) = self.decimator.push(sample as f32) else {
+                continue;
+            };
+
+            if let Some(hr) = self.heart_rate.push(x) {
+                log::debug!(
+                    "Heart rate[{}]: {:.1} BPM ({:.2})",
+                    self.side,
+                    hr.bpm,
+                    hr.confidence
+                );
+                self.hrv.set_heart_rate(hr.bpm);
+                if reliable {
+                    publish_high_freq(client, self.topics.heart_rate, format!("{:.1}", hr.bpm));
+                    publish_high_freq(
+                        client,
+                        self.topics.heart_rate_confidence,
+                        format!("{:.2}", hr.confidence),
+                    );
+                    if hr.confidence >= MIN_HR_CONFIDENCE {
+                        self.latest.heart_rate = Some(hr.bpm);
+                        self.epoch.hr_sum += hr.bpm;
+                        self.epoch.hr_count += 1;
+                    }
+                }
+            }
+
+            if let Some(br) = self.breathing.push(x) {
+                log::debug!(
+                    "Breathing rate[{}]: {:.1} br/min ({:.2})",
+                    self.side,
+                    br.bpm,
+                    br.confidence
+                );
+                // don't mark breathing as irregular over lost packets
+                if reliable {
+                    publish_high_freq(client, self.topics.breathing_rate, format!("{:.1}", br.bpm));
+                    publish_high_freq(client, self.topics.breathing_ok, br.is_ok().to_string());
+                    if br.is_ok() {
+                        self.latest.breathing_rate = Some(br.bpm);
+                        self.epoch.br_sum += br.bpm;
+                        self.epoch.br_count += 1;
+                    } else {
+                        self.epoch.br_poor = true;
+                    }
+                }
+            }
+
+            if let Some(hrv) = self.hrv.push(x) {
+                log::debug!(
+                    "HRV[{}]: RMSSD {:.1}ms SDNN {:.1}ms",
+                    self.side,
+                    hrv.rmssd,
+                    hrv.sdnn
+                );
+                if let Some(nightly) = self.add_hrv(current_night(timezone), &hrv, reliable) {
+                    publish_high_freq(client, self.topics.hrv_rmssd, format!("{:.1}", hrv.rmssd));
+                    publish_high_freq(client, self.topics.hrv_sdnn, format!("{:.1}", hrv.sdnn));
+                    publish_high_freq(
+                        client,
+                        self.topics.hrv_rmssd_nightly,
+                        format!("{:.1}", nightly.rmssd),
+                    );
+                    publish_high_freq(
+                        client,
+                        self.topics.hrv_sdnn_nightly,
+                        format!("{:.1}", nightly.sdnn),
+                    );
+                }
+            }
+
+            if let Some(update) = self.movement.push(x) {
+                if update.moving {
+                    self.epoch.moving_secs += 1;
+                }
+
+                if update.toss_turn {
+                    let count = self.nightly_toss_turns.add(current_night(timezone), 1);
+                    log::debug!("Toss-and-turn[{}] ({count} tonight)", self.side);
+                    publish_high_freq(client, self.topics.toss_turns, count.to_string());
+                }
+
+                if let Some(restlessness) = update.restlessness {
+                    let count = self.nightly_toss_turns.add(current_night(timezone), 0);
+                    self.latest.restlessness = Some(restlessness);
+                    publish_high_freq(
+                        client,
+                        self.topics.restlessness,
+                        format!("{restlessness:.0}"),
+                    );
+                    publish_high_freq(client, self.topics.toss_turns, count.to_string());
+                }
+            }
+        }
+    }
+
+    /// unreliable measurements are left out of `latest` & the nightly average
+    /// returns the nightly average
+    fn add_hrv(&mut self, night: Date, hrv: &Hrv, reliable: bool) -> Option<Hrv> {
+        if !reliable {
+            return None;
+        }
+
+        self.latest.hrv_rmssd = Some(hrv.rmssd);
+        Some(self.nightly_hrv.add(night, hrv))
+    }
+
+    fn take_epoch(&mut self) -> Epoch {
+        let stats = std::mem::take(&mut self.epoch);
+        Epoch {
+            occupied: self.occupied && !stats.vacated,
+            moving_secs: stats.moving_secs,
+            heart_rate: (stats.hr_count > 0).then(|| stats.hr_sum / stats.hr_count as f32),
+            breathing_rate: (stats.br_count > 0).then(|| stats.br_sum / stats.br_count as f32),
+            breathing_regular: stats.br_count > 0 && !stats.br_poor,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.decimator.reset();
+        self.heart_rate.reset();
+        self.breathing.reset();
+        self.hrv.reset();
+        self.movement.reset();
+        self.latest = LatestBiometrics::default();
+    }
+}
+
+/// nights run noon to noon, named by the date they start on
+pub fn current_night(timezone: &TimeZone) -> Date {
+    Timestamp::now()
+        .to_zoned(timezone.clone())
+        .saturating_sub(Span::new().hours(12))
+        .date()
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::civil::date;
+
+    use super::*;
+
+    #[test]
+    fn test_unreliable_hrv() {
+        let mut side = SideBiometrics::new(BedSide::Left, LEFT_TOPICS);
+        let night = date(2025, 1, 1);
+        let a = Hrv {
+            rmssd: 40.0,
+            sdnn: 60.0,
+        };
+        let b = Hrv {
+            rmssd: 90.0,
+            sdnn: 120.0,
+        };
+
+        assert_eq!(side.add_hrv(night, &a, true), Some(a.clone()));
+        assert_eq!(side.add_hrv(night, &b, false), None);
+        assert_eq!(side.latest.hrv_rmssd, Some(40.0));
+
+        // the unreliable window didn't move the average
+        assert_eq!(side.add_hrv(night, &a, true), Some(a));
+    }
+}
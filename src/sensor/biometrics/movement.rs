@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+
+use jiff::civil::Date;
+
+use super::SAMPLE_RATE;
+
+const SECOND_LEN: usize = SAMPLE_RATE as usize;
+/// seconds of per-second piezo ranges used for the quiet baseline
+const BASELINE_LEN: usize = 60;
+/// a second is moving if its piezo range exceeds the baseline by this factor
+const PIEZO_RATIO: f32 = 3.0;
+/// a second is moving if the summed capacitance change of a side exceeds this
+const CAPACITANCE_THRESHOLD: u16 = 30;
+/// consecutive moving seconds needed for a toss-and-turn
+const TOSS_TURN_SECS: u32 = 3;
+/// seconds, bouts closer together than this count as the same toss-and-turn
+const TOSS_TURN_GAP: u32 = 30;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MovementUpdate {
+    /// moved during this second
+    pub moving: bool,
+    /// a new toss-and-turn started this second
+    pub toss_turn: bool,
+    /// % of the last minute spent moving, set once a minute
+    pub restlessness: Option<f32>,
+}
+
+/// Detects movement per second from piezo amplitude excursions and
+/// capacitance changes
+pub struct MovementTracker {
+    /// piezo min & max of the current second
+    range: Option<(f32, f32)>,
+    samples: usize,
+    ranges: VecDeque<f32>,
+    last_capacitance: Option<[u16; 3]>,
+    capacitance_moving: bool,
+    seconds: u32,
+    moving_secs: u32,
+    bout_secs: u32,
+    /// seconds since the last toss-and-turn
+    since_toss_turn: Option<u32>,
+}
+
+impl MovementTracker {
+    pub fn new() -> Self {
+        Self {
+            range: None,
+            samples: 0,
+            ranges: VecDeque::with_capacity(BASELINE_LEN),
+            last_capacitance: None,
+            capacitance_moving: false,
+            seconds: 0,
+            moving_secs: 0,
+            bout_secs: 0,
+            since_toss_turn: None,
+        }
+    }
+
+    /// takes in this side's 3 capacitance channels
+    pub fn push_capacitance(&mut self, values: &[u16]) {
+        let values: [u16; 3] = values.try_into().expect("expected 3 capacitance channels");
+        if let Some(last) = self.last_capacitance {
+            let change: u16 = values
+                .iter()
+                .zip(last)
+                .map(|(a, b)| a.abs_diff(b))
+                .fold(0, u16::saturating_add);
+            if change > CAPACITANCE_THRESHOLD {
+                self.capacitance_moving = true;
+            }
+        }
+        self.last_capacitance = Some(values);
+    }
+
+    /// takes in a decimated piezo sample (at `SAMPLE_RATE`)
+    /// returns an update at the end of every second
+    pub fn push(&mut self, x: f32) -> Option<MovementUpdate> {
+        self.range = Some(match self.range {
+            Some((lo, hi)) => (lo.min(x), hi.max(x)),
+            None => (x, x),
+        });
+        self.samples += 1;
+
+        if self.samples < SECOND_LEN {
+            return None;
+        }
+
+        let (lo, hi) = self.range.take().unwrap();
+        self.samples = 0;
+        Some(self.on_second(hi - lo))
+    }
+
+    fn on_second(&mut self, range: f32) -> MovementUpdate {
+        let piezo_moving = self.baseline().is_some_and(|b| range > b * PIEZO_RATIO);
+        let moving = piezo_moving || self.capacitance_moving;
+        self.capacitance_moving = false;
+
+        if self.ranges.len() == BASELINE_LEN {
+            self.ranges.pop_front();
+        }
+        self.ranges.push_back(range);
+
+        let mut update = MovementUpdate {
+            moving,
+            ..Default::default()
+        };
+
+        self.since_toss_turn = self.since_toss_turn.map(|s| s.saturating_add(1));
+        if moving {
+            self.moving_secs += 1;
+            self.bout_secs += 1;
+
+            if self.bout_secs == TOSS_TURN_SECS
+                && self.since_toss_turn.is_none_or(|s| s > TOSS_TURN_GAP)
+            {
+                update.toss_turn = true;
+            }
+        } else {
+            self.bout_secs = 0;
+        }
+
+        // keep extending the gap while the bout continues
+        if self.bout_secs >= TOSS_TURN_SECS {
+            self.since_toss_turn = Some(0);
+        }
+
+        self.seconds += 1;
+        if self.seconds == 60 {
+            update.restlessness = Some(self.moving_secs as f32 / 60.0 * 100.0);
+            self.seconds = 0;
+            self.moving_secs = 0;
+        }
+
+        update
+    }
+
+    /// median per-second piezo range, robust to short bouts of movement
+    fn baseline(&self) -> Option<f32> {
+        if self.ranges.len() < BASELINE_LEN / 4 {
+            return None;
+        }
+        let mut sorted: Vec<f32> = self.ranges.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        Some(sorted[sorted.len() / 2])
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Toss-and-turn count over a night
+#[derive(Default)]
+pub struct NightlyTossTurns {
+    night: Option<Date>,
+    count: u32,
+}
+
+impl NightlyTossTurns {
+    /// adds `n` toss-and-turns, starting over when `night` changes
+    /// returns the nightly count
+    pub fn add(&mut self, night: Date, n: u32) -> u32 {
+        if self.night != Some(night) {
+            self.night = Some(night);
+            self.count = 0;
+        }
+        self.count += n;
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use jiff::civil::date;
+
+    use super::*;
+
+    /// runs `secs` seconds of piezo through the tracker, with large excursions while `moving(sec)`
+    fn run(
+        tracker: &mut MovementTracker,
+        secs: usize,
+        moving: impl Fn(usize) -> bool,
+    ) -> Vec<MovementUpdate> {
+        let mut updates = vec![];
+        for i in 0..secs * SECOND_LEN {
+            let t = i as f32 / SAMPLE_RATE;
+            let amp = if moving(i / SECOND_LEN) {
+                2000.0
+            } else {
+                100.0
+            };
+            if let Some(u) = tracker.push(32768.0 + amp * (2.0 * PI * 1.2 * t).sin()) {
+                updates.push(u);
+            }
+        }
+        updates
+    }
+
+    #[test]
+    fn test_still() {
+        let mut tracker = MovementTracker::new();
+        let updates = run(&mut tracker, 120, |_| false);
+        assert_eq!(updates.len(), 120);
+        assert!(updates.iter().all(|u| !u.toss_turn));
+        let minutes: Vec<f32> = updates.iter().filter_map(|u| u.restlessness).collect();
+        assert_eq!(minutes, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_toss_turn() {
+        let mut tracker = MovementTracker::new();
+        // 5 second bout at 30s, second bout at 40s (same toss-and-turn), third at 100s
+        let updates = run(&mut tracker, 120, |s| {
+            (30..35).contains(&s) || (40..45).contains(&s) || (100..106).contains(&s)
+        });
+
+        assert_eq!(updates.iter().filter(|u| u.toss_turn).count(), 2);
+        let minutes: Vec<f32> = updates.iter().filter_map(|u| u.restlessness).collect();
+        assert_eq!(minutes, vec![10.0 / 60.0 * 100.0, 6.0 / 60.0 * 100.0]);
+    }
+
+    #[test]
+    fn test_capacitance() {
+        let mut tracker = MovementTracker::new();
+        run(&mut tracker, 30, |_| false);
+
+        tracker.push_capacitance(&[500, 500, 500]);
+        tracker.push_capacitance(&[500, 520, 500]);
+        let updates = run(&mut tracker, 1, |_| false);
+        assert_eq!(tracker.moving_secs, 0, "{updates:?}");
+
+        tracker.push_capacitance(&[540, 500, 500]);
+        run(&mut tracker, 1, |_| false);
+        assert_eq!(tracker.moving_secs, 1);
+    }
+
+    #[test]
+    fn test_nightly_toss_turns() {
+        let mut nightly = NightlyTossTurns::default();
+        assert_eq!(nightly.add(date(2025, 1, 1), 1), 1);
+        assert_eq!(nightly.add(date(2025, 1, 1), 0), 1);
+        assert_eq!(nightly.add(date(2025, 1, 1), 1), 2);
+        assert_eq!(nightly.add(date(2025, 1, 2), 0), 0);
+    }
+}
@@ -0,0 +1,163 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+/// Dominant frequency found in a window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peak {
+    /// Hz
+    pub freq: f32,
+    /// fraction (0-1) of the in-band power that belongs to the peak
+    pub confidence: f32,
+}
+
+/// Finds the dominant frequency of a window using a zero-padded FFT
+pub struct Spectrum {
+    fft: Arc<dyn Fft<f32>>,
+    sample_rate: f32,
+    buf: Vec<Complex<f32>>,
+    power: Vec<f32>,
+}
+
+impl Spectrum {
+    pub fn new(size: usize, sample_rate: f32) -> Self {
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(size),
+            sample_rate,
+            buf: vec![Complex::default(); size],
+            power: vec![0.0; size / 2],
+        }
+    }
+
+    fn bin_width(&self) -> f32 {
+        self.sample_rate / self.buf.len() as f32
+    }
+
+    /// Finds the strongest fundamental within `band` (Hz).
+    /// Each candidate is scored by the sum of its first `harmonics` harmonics
+    /// so that signals with a strong second harmonic (IE BCG) lock onto the fundamental.
+    pub fn dominant(
+        &mut self,
+        samples: &[f32],
+        band: (f32, f32),
+        harmonics: usize,
+    ) -> Option<Peak> {
+        let n = samples.len();
+        if n < 2 || n > self.buf.len() {
+            return None;
+        }
+
+        // remove mean, apply Hann window, zero pad
+        let mean = samples.iter().sum::<f32>() / n as f32;
+        for (i, c) in self.buf.iter_mut().enumerate() {
+            *c = match samples.get(i) {
+                Some(x) => {
+                    let w = 0.5 - 0.5 * (2.0 * PI * i as f32 / (n - 1) as f32).cos();
+                    Complex::new((x - mean) * w, 0.0)
+                }
+                None => Complex::default(),
+            };
+        }
+
+        self.fft.process(&mut self.buf);
+        for (p, c) in self.power.iter_mut().zip(&self.buf) {
+            *p = c.norm_sqr();
+        }
+
+        let bin_width = self.bin_width();
+        let lo = (band.0 / bin_width).ceil() as usize;
+        let hi = ((band.1 / bin_width).floor() as usize).min(self.power.len() - 1);
+        if lo >= hi {
+            return None;
+        }
+
+        let score =
+            |k: usize| -> f32 { (1..=harmonics).filter_map(|h| self.power.get(k * h)).sum() };
+
+        let (peak_bin, _) = (lo..=hi)
+            .map(|k| (k, score(k)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+        // Hann main lobe is 2 bins wide (per side) in the un-padded spectrum
+        let lobe = (2 * self.buf.len()).div_ceil(n);
+        let lobe_power = |center: usize| -> f32 {
+            let start = center.saturating_sub(lobe);
+            let end = (center + lobe).min(self.power.len() - 1);
+            self.power[start..=end].iter().sum()
+        };
+
+        let total: f32 = self.power[lo..=(hi * harmonics).min(self.power.len() - 1)]
+            .iter()
+            .sum();
+        if total <= f32::EPSILON {
+            return None;
+        }
+
+        let peak_power: f32 = (1..=harmonics)
+            .filter(|h| peak_bin * h < self.power.len())
+            .map(|h| lobe_power(peak_bin * h))
+            .sum();
+
+        Some(Peak {
+            freq: self.interpolate(peak_bin) * bin_width,
+            confidence: (peak_power / total).min(1.0),
+        })
+    }
+
+    /// parabolic interpolation around `bin` for sub-bin accuracy
+    fn interpolate(&self, bin: usize) -> f32 {
+        if bin == 0 || bin + 1 >= self.power.len() {
+            return bin as f32;
+        }
+        let (a, b, c) = (
+            self.power[bin - 1].sqrt(),
+            self.power[bin].sqrt(),
+            self.power[bin + 1].sqrt(),
+        );
+        let denom = a - 2.0 * b + c;
+        if denom.abs() <= f32::EPSILON {
+            return bin as f32;
+        }
+        bin as f32 + 0.5 * (a - c) / denom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, sample_rate: f32, n: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| (2.0 * PI * freq * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_dominant() {
+        let mut spec = Spectrum::new(8192, 100.0);
+        let peak = spec
+            .dominant(&sine(1.2, 100.0, 2000), (0.7, 3.0), 1)
+            .unwrap();
+        assert!((peak.freq - 1.2).abs() < 0.02, "got {}", peak.freq);
+        assert!(peak.confidence > 0.9, "got {}", peak.confidence);
+    }
+
+    #[test]
+    fn test_dominant_harmonics() {
+        // second harmonic is stronger than the fundamental
+        let fund = sine(1.0, 100.0, 2000);
+        let second = sine(2.0, 100.0, 2000);
+        let samples: Vec<f32> = fund.iter().zip(&second).map(|(a, b)| 0.6 * a + b).collect();
+
+        let mut spec = Spectrum::new(8192, 100.0);
+        let peak = spec.dominant(&samples, (0.7, 3.0), 2).unwrap();
+        assert!((peak.freq - 1.0).abs() < 0.02, "got {}", peak.freq);
+    }
+
+    #[test]
+    fn test_dominant_flat() {
+        let mut spec = Spectrum::new(8192, 100.0);
+        assert_eq!(spec.dominant(&[1.0; 2000], (0.7, 3.0), 1), None);
+        assert_eq!(spec.dominant(&[], (0.7, 3.0), 1), None);
+    }
+}
@@ -1,18 +1,23 @@
 use std::io::ErrorKind;
 use std::time::Duration;
 
-use crate::common::codec::PacketCodec;
+use crate::bus::{Bus, SensorEvent};
+use crate::common::codec::{CodecStats, PacketCodec};
 use crate::common::packet::BedSide;
 use crate::common::serial::{DeviceMode, SerialError, create_framed_port};
 use crate::config::{Config, SidesConfig};
+use crate::history::HistoryRecorder;
+use crate::sensor::biometrics::BiometricsManager;
 use crate::sensor::command::{AlarmCommand, AlarmPattern};
 use crate::sensor::presence::PresenseManager;
+use crate::sensor::recorder::RawRecorder;
 use crate::sensor::state::{PIEZO_FREQ, PIEZO_GAIN, SensorState};
-use crate::sensor::{SensorCommand, SensorPacket};
+use crate::sensor::stats::{SensorStats, Sequence};
+use crate::sensor::{SensorAction, SensorCommand, SensorPacket};
+use crate::sleep::SleepManager;
 use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
-use jiff::civil::Time;
-use jiff::{Span, Timestamp};
+use jiff::{Span, Timestamp, Zoned};
 use rumqttc::AsyncClient;
 use thiserror::Error;
 use tokio::sync::{mpsc, watch};
@@ -27,7 +32,7 @@ const TIMEOUT: Duration = Duration::from_secs(5);
 
 type Reader = SplitStream<Framed<SerialStream, PacketCodec<SensorPacket>>>;
 type Writer = SplitSink<Framed<SerialStream, PacketCodec<SensorPacket>>, SensorCommand>;
-type CommandCheck = fn(&SensorState, &Time, &bool, &SidesConfig) -> Option<SensorCommand>;
+type CommandCheck = fn(&SensorState, &Zoned, &bool, &SidesConfig) -> Option<SensorCommand>;
 
 struct CommandScheduler {
     cmds: Vec<RegisteredCommand>,
@@ -55,22 +60,33 @@ pub async fn run(
     port: &'static str,
     config_tx: watch::Sender<Config>,
     mut config_rx: watch::Receiver<Config>,
-    mut calibrate_rx: mpsc::Receiver<()>,
+    mut action_rx: mpsc::Receiver<SensorAction>,
+    bus: Bus,
     mut client: AsyncClient,
 ) -> Result<(), SensorError> {
     log::info!("Initializing Sensor Subsystem...");
 
     let mut presense_man = PresenseManager::new(config_tx, config_rx.clone(), client.clone());
+    let mut biometrics =
+        BiometricsManager::new(client.clone(), config_rx.borrow().timezone.clone());
 
-    let mut state = SensorState::default();
+    let mut state = SensorState {
+        unit: config_rx.borrow().unit,
+        ..Default::default()
+    };
     state.publish_reset(&mut client).await;
 
-    let (writer, mut reader) = run_discovery(port, &mut client, &mut state).await?;
+    let codec_stats = CodecStats::default();
+    let (writer, mut reader) = run_discovery(port, &mut client, &mut state, &codec_stats).await?;
     log::info!("Connected");
+    let mut stats = SensorStats::new(client.clone(), codec_stats);
 
     let cfg = config_rx.borrow_and_update();
-    let timezone = cfg.timezone.clone();
+    let mut timezone = cfg.timezone.clone();
     let mut scheduler = CommandScheduler::new(cfg.away_mode, cfg.profile.clone(), writer);
+    let mut sleep_man = SleepManager::new(client.clone(), &cfg.profile);
+    let mut history = HistoryRecorder::new(timezone.clone(), bus.snapshot());
+    let mut recorder = RawRecorder::default();
     drop(cfg);
 
     let mut interval = interval(Duration::from_millis(50));
@@ -80,11 +96,36 @@ pub async fn run(
         tokio::select! {
             Some(result) = reader.next() => match result {
                 Ok(packet) => {
-                    if let SensorPacket::Capacitance(data) = &packet {
-                        presense_man.update(data);
+                    recorder.record(&packet);
+
+                    match &packet {
+                        SensorPacket::Capacitance(data) => {
+                            stats.capacitance.push(data.sequence);
+                            presense_man.update(data);
+                            biometrics.update_capacitance(data, presense_man.state());
+                            if let Some(presence) = presense_man.state() {
+                                sleep_man.update_presence(presence);
+                                bus.send_sensor(SensorEvent::Presence(presence.clone()));
+                            }
+                        }
+                        SensorPacket::Piezo(data) => {
+                            let seq = stats.piezo.push(data.sequence);
+                            if seq != Sequence::Duplicate {
+                                biometrics.update_piezo(data, presense_man.state());
+                            }
+                        }
+                        SensorPacket::Temperature(data) => {
+                            sleep_man.update_temperature(data);
+                            bus.send_sensor(SensorEvent::BedTemperature(data.clone()));
+                        }
+                        _ => {}
                     }
 
                     state.handle_packet(&mut client, packet).await;
+                    for side in [BedSide::Left, BedSide::Right] {
+                        let running = state.get_alarm_for_side(&side);
+                        bus.send_sensor(SensorEvent::Alarm { side, running });
+                    }
 
                     last_recv = Instant::now();
                 }
@@ -95,20 +136,37 @@ pub async fn run(
 
             _ = interval.tick() => {
                 // this is not expensive so its fine to do at 20hz
-                let now = Timestamp::now().to_zoned(timezone.clone()).time();
+                let now = Timestamp::now().to_zoned(timezone.clone());
                 let _ = scheduler.update(&state, &now).await?;
+                stats.update();
+                biometrics.set_reliable(stats.reliable());
+                sleep_man.update(&mut biometrics, state.unit).await;
+                history.update(&biometrics);
 
                 if Instant::now().duration_since(last_recv) > TIMEOUT {
                     break Err(SensorError::Timeout);
                 }
             }
 
-            Some(_) = calibrate_rx.recv() => presense_man.start_calibration(),
+            Some(action) = action_rx.recv() => match action {
+                SensorAction::Calibrate => presense_man.start_calibration(),
+                SensorAction::ExportHistory(night) => history.export(&client, night, state.unit),
+                SensorAction::RecordRaw(enabled) => {
+                    recorder.set_recording(&mut client, &timezone, enabled).await;
+                }
+            },
 
             Ok(_) = config_rx.changed() => {
                 let cfg = config_rx.borrow();
                 scheduler.away_mode = cfg.away_mode;
                 scheduler.sides_config = cfg.profile.clone();
+                sleep_man.set_sides_config(&cfg.profile);
+                state.unit = cfg.unit;
+                if cfg.timezone != timezone {
+                    timezone = cfg.timezone.clone();
+                    biometrics.set_timezone(timezone.clone());
+                    history.set_timezone(timezone.clone());
+                }
             }
         }
     }
@@ -226,7 +284,7 @@ impl CommandScheduler {
 
     /// finds the first command to send and sends it
     /// returns if it send a command
-    async fn update(&mut self, state: &SensorState, time: &Time) -> Result<bool, SensorError> {
+    async fn update(&mut self, state: &SensorState, time: &Zoned) -> Result<bool, SensorError> {
         let now = Instant::now();
 
         // find command to send
@@ -249,19 +307,24 @@ impl CommandScheduler {
 }
 
 /// alarm runs from (wake - alarm_offset) to ((wake - alarm_offset) + alarm_duration)
+/// using the schedule(s) waking up today
 fn get_alarm_cmd(
     state: &SensorState,
-    now: &Time,
+    now: &Zoned,
     sides_config: &SidesConfig,
     side: &BedSide,
 ) -> Option<SensorCommand> {
-    let cfg = sides_config.get_side(side);
-    let alarm_cfg = cfg.alarm.as_ref()?;
-    let alarm_start = cfg.wake - Span::new().seconds(alarm_cfg.offset);
-    let alarm_end = alarm_start + Span::new().seconds(alarm_cfg.duration);
+    let schedules = sides_config.get_side(side).waking_today(now);
+    let now = now.time();
+    let active_alarm = schedules.iter().find_map(|cfg| {
+        let alarm_cfg = cfg.alarm.as_ref()?;
+        let alarm_start = cfg.wake - Span::new().seconds(alarm_cfg.offset);
+        let alarm_end = alarm_start + Span::new().seconds(alarm_cfg.duration);
+        (now > alarm_start && now < alarm_end).then_some(alarm_cfg)
+    });
     let alarm_running = state.get_alarm_for_side(side);
 
-    if now > &alarm_start && now < &alarm_end {
+    if let Some(alarm_cfg) = active_alarm {
         if !alarm_running {
             log::info!("Alarm[{side}] requesting to start");
             return Some(SensorCommand::SetAlarm(AlarmCommand {
@@ -291,10 +354,11 @@ async fn run_discovery(
     port: &'static str,
     client: &mut AsyncClient,
     state: &mut SensorState,
+    codec_stats: &CodecStats,
 ) -> Result<(Writer, Reader), SerialError> {
     // try bootloader first
     if let Ok((mut writer, mut reader)) =
-        ping_device(port, client, state, DeviceMode::Bootloader).await
+        ping_device(port, client, state, codec_stats, DeviceMode::Bootloader).await
     {
         writer
             .send(SensorCommand::JumpToFirmware)
@@ -304,18 +368,21 @@ async fn run_discovery(
         // wait for mode switch
         wait_for_mode(&mut reader, client, state, DeviceMode::Firmware).await?;
 
-        return Ok(create_framed_port::<SensorPacket>(port, FIRMWARE_BAUD)?.split());
+        return Ok(
+            create_framed_port::<SensorPacket>(port, FIRMWARE_BAUD, codec_stats.clone())?.split(),
+        );
     }
 
     // try firmware (happens if program was recently running)
     log::info!("Trying Firmware mode");
-    ping_device(port, client, state, DeviceMode::Firmware).await
+    ping_device(port, client, state, codec_stats, DeviceMode::Firmware).await
 }
 
 async fn ping_device(
     port: &'static str,
     client: &mut AsyncClient,
     state: &mut SensorState,
+    codec_stats: &CodecStats,
     mode: DeviceMode,
 ) -> Result<(Writer, Reader), SerialError> {
     let baud = if mode == DeviceMode::Bootloader {
@@ -323,7 +390,8 @@ async fn ping_device(
     } else {
         FIRMWARE_BAUD
     };
-    let (mut writer, mut reader) = create_framed_port::<SensorPacket>(port, baud)?.split();
+    let (mut writer, mut reader) =
+        create_framed_port::<SensorPacket>(port, baud, codec_stats.clone())?.split();
 
     for _ in 0..3 {
         writer
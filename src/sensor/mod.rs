@@ -1,9 +1,22 @@
+pub mod biometrics;
 pub mod command;
 pub mod manager;
 pub mod packet;
 pub mod presence;
+pub mod recorder;
 pub mod state;
+pub mod stats;
+
+use jiff::civil::Date;
 
 pub use command::SensorCommand;
 pub use manager::{PORT, run};
 pub use packet::SensorPacket;
+
+/// MQTT actions handled by the Sensor task
+#[derive(Debug)]
+pub enum SensorAction {
+    Calibrate,
+    ExportHistory(Date),
+    RecordRaw(bool),
+}
@@ -2,7 +2,7 @@ use bytes::BytesMut;
 use hex_literal::hex;
 
 use crate::common::packet::{
-    self, HardwareInfo, Packet, PacketError, invalid_structure, validate_packet_at_least,
+    self, BedSide, HardwareInfo, Packet, PacketError, invalid_structure, validate_packet_at_least,
     validate_packet_size,
 };
 
@@ -52,6 +52,18 @@ pub struct TemperatureData {
     pub microcontroller: u16,
 }
 
+impl TemperatureData {
+    /// average of a side's bed sensors, only the first 6 are used (3 per side)
+    /// centidegrees celcius
+    pub fn bed_side(&self, side: &BedSide) -> u16 {
+        let temps = match side {
+            BedSide::Left => &self.bed[0..3],
+            BedSide::Right => &self.bed[3..6],
+        };
+        (temps.iter().map(|&t| t as u32).sum::<u32>() / temps.len() as u32) as u16
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct PiezoData {
     pub freq: u32,
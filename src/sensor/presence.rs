@@ -1,3 +1,4 @@
+use crate::common::packet::BedSide;
 use crate::config::{Config, PresenceConfig};
 use crate::mqtt::publish_high_freq;
 use crate::sensor::packet::CapacitanceData;
@@ -21,6 +22,15 @@ pub struct PresenceState {
     pub right: bool,
 }
 
+impl PresenceState {
+    pub fn get_side(&self, side: &BedSide) -> bool {
+        match side {
+            BedSide::Left => self.left,
+            BedSide::Right => self.right,
+        }
+    }
+}
+
 pub struct PresenseManager {
     config_tx: watch::Sender<Config>,
     config_rx: watch::Receiver<Config>,
@@ -58,6 +68,11 @@ impl PresenseManager {
         }
     }
 
+    /// None if presence is not configured yet
+    pub fn state(&self) -> Option<&PresenceState> {
+        self.last_state.as_ref()
+    }
+
     pub fn update(&mut self, data: &CapacitanceData) {
         if self.config.is_some() {
             self.update_presence(data);
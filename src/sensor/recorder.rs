@@ -0,0 +1,227 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use jiff::{Timestamp, tz::TimeZone};
+use rumqttc::AsyncClient;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mqtt::{ERROR, SUCCESS, publish_guaranteed_wait, publish_result},
+    sensor::SensorPacket,
+};
+
+/// relative to the working directory
+const RECORDINGS_DIR: &str = "recordings";
+
+pub const TOPIC_RECORD_RAW: &str = "opensleep/actions/record_raw";
+const TOPIC_RECORDING: &str = "opensleep/state/sensor/recording";
+
+/// One raw packet, written as a CBOR sequence (one item after another)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RawRecord {
+    Piezo {
+        /// unix ms
+        ts: i64,
+        seq: u32,
+        freq: u32,
+        gain: (u16, u16),
+        left: Vec<u16>,
+        right: Vec<u16>,
+    },
+    Capacitance {
+        /// unix ms
+        ts: i64,
+        seq: u32,
+        values: [u16; 6],
+    },
+    Temperature {
+        /// unix ms
+        ts: i64,
+        /// assigned by the recorder, Sensor doesn't send one
+        seq: u32,
+        bed: [u16; 8],
+        ambient: u16,
+        humidity: u16,
+        mcu: u16,
+    },
+}
+
+/// Opt-in recorder of the raw Sensor streams for offline analysis
+#[derive(Default)]
+pub struct RawRecorder {
+    writer: Option<BufWriter<File>>,
+    path: Option<PathBuf>,
+    temperature_seq: u32,
+    records: u64,
+}
+
+impl RawRecorder {
+    pub async fn set_recording(
+        &mut self,
+        client: &mut AsyncClient,
+        timezone: &TimeZone,
+        enabled: bool,
+    ) {
+        let (status, msg) = match (enabled, self.writer.is_some()) {
+            (true, true) => (ERROR, "already recording".to_string()),
+            (false, false) => (ERROR, "not recording".to_string()),
+            (true, false) => match self.start(timezone) {
+                Ok(path) => (SUCCESS, format!("recording to {}", path.display())),
+                Err(e) => {
+                    log::error!("Failed to start raw recording: {e}");
+                    (ERROR, format!("failed to start recording: {e}"))
+                }
+            },
+            (false, true) => {
+                let records = self.records;
+                self.stop();
+                (SUCCESS, format!("stopped recording ({records} records)"))
+            }
+        };
+
+        publish_result(client, "record_raw", status, msg).await;
+        publish_guaranteed_wait(
+            client,
+            TOPIC_RECORDING,
+            true,
+            self.writer.is_some().to_string(),
+        )
+        .await;
+    }
+
+    fn start(&mut self, timezone: &TimeZone) -> Result<PathBuf, std::io::Error> {
+        fs::create_dir_all(RECORDINGS_DIR)?;
+        let name = Timestamp::now()
+            .to_zoned(timezone.clone())
+            .strftime("%Y-%m-%dT%H-%M-%S.cbor")
+            .to_string();
+        let path = PathBuf::from(RECORDINGS_DIR).join(name);
+
+        log::info!("Raw recording started: {}", path.display());
+        self.writer = Some(BufWriter::new(File::create(&path)?));
+        self.path = Some(path.clone());
+        self.temperature_seq = 0;
+        self.records = 0;
+        Ok(path)
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut writer) = self.writer.take()
+            && let Err(e) = writer.flush()
+        {
+            log::error!("Failed to flush raw recording: {e}");
+        }
+        if let Some(path) = self.path.take() {
+            log::info!(
+                "Raw recording stopped: {} ({} records)",
+                path.display(),
+                self.records
+            );
+        }
+    }
+
+    /// writes the packet if recording
+    pub fn record(&mut self, packet: &SensorPacket) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+
+        let Some(record) = RawRecord::from_packet(packet, &mut self.temperature_seq) else {
+            return;
+        };
+
+        if let Err(e) = cbor4ii::serde::to_writer(writer, &record) {
+            log::error!("Failed to write raw recording, stopping: {e}");
+            self.stop();
+            return;
+        }
+        self.records += 1;
+    }
+}
+
+impl RawRecord {
+    fn from_packet(packet: &SensorPacket, temperature_seq: &mut u32) -> Option<Self> {
+        let ts = Timestamp::now().as_millisecond();
+        Some(match packet {
+            SensorPacket::Piezo(d) => RawRecord::Piezo {
+                ts,
+                seq: d.sequence,
+                freq: d.freq,
+                gain: d.gain,
+                left: d.left_samples.clone(),
+                right: d.right_samples.clone(),
+            },
+            SensorPacket::Capacitance(d) => RawRecord::Capacitance {
+                ts,
+                seq: d.sequence,
+                values: d.values,
+            },
+            SensorPacket::Temperature(d) => {
+                *temperature_seq = temperature_seq.wrapping_add(1);
+                RawRecord::Temperature {
+                    ts,
+                    seq: *temperature_seq,
+                    bed: d.bed,
+                    ambient: d.ambient,
+                    humidity: d.humidity,
+                    mcu: d.microcontroller,
+                }
+            }
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sensor::packet::{CapacitanceData, PiezoData, TemperatureData};
+
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let packets = [
+            SensorPacket::Piezo(PiezoData {
+                freq: 1000,
+                sequence: 7,
+                gain: (400, 400),
+                left_samples: vec![1, 2, 3],
+                right_samples: vec![4, 5, 6],
+            }),
+            SensorPacket::Capacitance(CapacitanceData {
+                sequence: 8,
+                values: [1, 2, 3, 4, 5, 6],
+            }),
+            SensorPacket::Temperature(TemperatureData {
+                bed: [3000; 8],
+                ambient: 2100,
+                humidity: 4000,
+                microcontroller: 3500,
+            }),
+            SensorPacket::Message("ignored".to_string()),
+        ];
+
+        let mut seq = 0;
+        let mut buf = vec![];
+        let mut written = vec![];
+        for packet in &packets {
+            if let Some(record) = RawRecord::from_packet(packet, &mut seq) {
+                cbor4ii::serde::to_writer(&mut buf, &record).unwrap();
+                written.push(record);
+            }
+        }
+        assert_eq!(written.len(), 3);
+
+        let mut reader = buf.as_slice();
+        let mut read = vec![];
+        while !reader.is_empty() {
+            read.push(cbor4ii::serde::from_reader::<RawRecord, _>(&mut reader).unwrap());
+        }
+        assert_eq!(read, written);
+        assert!(matches!(read[2], RawRecord::Temperature { seq: 1, .. }));
+    }
+}
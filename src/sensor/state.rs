@@ -5,6 +5,7 @@ use crate::{
         packet::{BedSide, HardwareInfo},
         serial::DeviceMode,
     },
+    config::TemperatureUnit,
     mqtt::{publish_guaranteed_wait, publish_high_freq},
     sensor::packet::SensorPacket,
 };
@@ -19,6 +20,8 @@ pub struct SensorState {
     pub piezo_enabled: bool,
     pub alarm_left_running: bool,
     pub alarm_right_running: bool,
+    /// of published temperatures
+    pub unit: TemperatureUnit,
 }
 
 pub const PIEZO_GAIN: u16 = 400;
@@ -168,14 +171,23 @@ impl SensorState {
                 publish_high_freq(
                     client,
                     TOPIC_BED_TEMP,
-                    format!(
-                        "{},{},{},{},{},{}",
-                        u.bed[0], u.bed[1], u.bed[2], u.bed[3], u.bed[4], u.bed[5]
-                    ),
+                    u.bed[..6]
+                        .iter()
+                        .map(|t| self.unit.format_reading(*t))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                );
+                publish_high_freq(
+                    client,
+                    TOPIC_AMBIENT_TEMP,
+                    self.unit.format_reading(u.ambient),
                 );
-                publish_high_freq(client, TOPIC_AMBIENT_TEMP, u.ambient.to_string());
                 publish_high_freq(client, TOPIC_HUMIDITY, u.humidity.to_string());
-                publish_high_freq(client, TOPIC_MCU_TEMP, u.microcontroller.to_string());
+                publish_high_freq(
+                    client,
+                    TOPIC_MCU_TEMP,
+                    self.unit.format_reading(u.microcontroller),
+                );
             }
             SensorPacket::Piezo(u) => {
                 let (enabled_changed, gain_changed, freq_changed);
@@ -0,0 +1,194 @@
+use std::time::{Duration, Instant};
+
+use rumqttc::AsyncClient;
+
+use crate::{common::codec::CodecStats, mqtt::publish_high_freq};
+
+const PUBLISH_INT: Duration = Duration::from_secs(10);
+/// biometrics are unreliable when more piezo packets than this are lost
+const MAX_GAP_RATE: f32 = 0.05;
+
+const TOPIC_PIEZO_DROPPED: &str = "opensleep/state/sensor/stats/piezo_dropped";
+const TOPIC_PIEZO_DUPLICATES: &str = "opensleep/state/sensor/stats/piezo_duplicates";
+const TOPIC_PIEZO_GAP_RATE: &str = "opensleep/state/sensor/stats/piezo_gap_rate";
+const TOPIC_CAPACITANCE_DROPPED: &str = "opensleep/state/sensor/stats/capacitance_dropped";
+const TOPIC_CAPACITANCE_DUPLICATES: &str = "opensleep/state/sensor/stats/capacitance_duplicates";
+const TOPIC_CHECKSUM_FAILURES: &str = "opensleep/state/sensor/stats/checksum_failures";
+const TOPIC_BIOMETRICS_RELIABLE: &str = "opensleep/state/sensor/stats/biometrics_reliable";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sequence {
+    /// next expected packet (or the first one)
+    Ok,
+    /// `n` packets were lost before this one
+    Gap(u32),
+    Duplicate,
+    /// went backwards, Sensor likely restarted
+    Reset,
+}
+
+/// Tracks gaps & duplicates in a stream's sequence numbers
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    last: Option<u32>,
+    /// since startup
+    pub dropped: u64,
+    pub duplicates: u64,
+    /// since the last `take_gap_rate`
+    interval_received: u32,
+    interval_dropped: u32,
+}
+
+impl SequenceTracker {
+    pub fn push(&mut self, seq: u32) -> Sequence {
+        let res = match self.last {
+            None => Sequence::Ok,
+            Some(last) if seq == last => Sequence::Duplicate,
+            Some(last) if seq < last => Sequence::Reset,
+            Some(last) if seq == last + 1 => Sequence::Ok,
+            Some(last) => Sequence::Gap(seq - last - 1),
+        };
+
+        match res {
+            Sequence::Duplicate => {
+                self.duplicates += 1;
+                return res;
+            }
+            Sequence::Gap(n) => {
+                self.dropped += n as u64;
+                self.interval_dropped = self.interval_dropped.saturating_add(n);
+            }
+            Sequence::Ok | Sequence::Reset => {}
+        }
+
+        self.last = Some(seq);
+        self.interval_received += 1;
+        res
+    }
+
+    /// fraction of packets lost since the last call
+    pub fn take_gap_rate(&mut self) -> Option<f32> {
+        let total = self.interval_received + self.interval_dropped;
+        let rate = (total > 0).then(|| self.interval_dropped as f32 / total as f32);
+        self.interval_received = 0;
+        self.interval_dropped = 0;
+        rate
+    }
+}
+
+/// Link health for the Sensor subsystem
+pub struct SensorStats {
+    client: AsyncClient,
+    codec: CodecStats,
+    /// failures from before connecting (wrong baud etc.) are ignored
+    checksum_base: u64,
+    pub piezo: SequenceTracker,
+    pub capacitance: SequenceTracker,
+    last_publish: Instant,
+    reliable: bool,
+}
+
+impl SensorStats {
+    pub fn new(client: AsyncClient, codec: CodecStats) -> Self {
+        Self {
+            client,
+            checksum_base: codec.checksum_failures(),
+            codec,
+            piezo: SequenceTracker::default(),
+            capacitance: SequenceTracker::default(),
+            last_publish: Instant::now(),
+            reliable: true,
+        }
+    }
+
+    /// whether few enough piezo packets are being lost for biometrics
+    pub fn reliable(&self) -> bool {
+        self.reliable
+    }
+
+    /// publishes every `PUBLISH_INT`
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        if now.duration_since(self.last_publish) < PUBLISH_INT {
+            return;
+        }
+        self.last_publish = now;
+
+        let gap_rate = self.piezo.take_gap_rate();
+        self.capacitance.take_gap_rate();
+
+        let reliable = gap_rate.is_none_or(|r| r <= MAX_GAP_RATE);
+        if reliable != self.reliable {
+            match reliable {
+                true => log::info!("Piezo stream recovered, biometrics reliable"),
+                false => log::warn!(
+                    "Piezo gap rate {:.1}%, biometrics unreliable",
+                    gap_rate.unwrap_or_default() * 100.0
+                ),
+            }
+            self.reliable = reliable;
+        }
+
+        let checksum_failures = self.codec.checksum_failures() - self.checksum_base;
+        let client = &mut self.client;
+        publish_high_freq(client, TOPIC_PIEZO_DROPPED, self.piezo.dropped.to_string());
+        publish_high_freq(
+            client,
+            TOPIC_PIEZO_DUPLICATES,
+            self.piezo.duplicates.to_string(),
+        );
+        if let Some(rate) = gap_rate {
+            publish_high_freq(client, TOPIC_PIEZO_GAP_RATE, format!("{:.1}", rate * 100.0));
+        }
+        publish_high_freq(
+            client,
+            TOPIC_CAPACITANCE_DROPPED,
+            self.capacitance.dropped.to_string(),
+        );
+        publish_high_freq(
+            client,
+            TOPIC_CAPACITANCE_DUPLICATES,
+            self.capacitance.duplicates.to_string(),
+        );
+        publish_high_freq(
+            client,
+            TOPIC_CHECKSUM_FAILURES,
+            checksum_failures.to_string(),
+        );
+        publish_high_freq(client, TOPIC_BIOMETRICS_RELIABLE, reliable.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence() {
+        let mut t = SequenceTracker::default();
+        assert_eq!(t.push(10), Sequence::Ok);
+        assert_eq!(t.push(11), Sequence::Ok);
+        assert_eq!(t.push(11), Sequence::Duplicate);
+        assert_eq!(t.push(14), Sequence::Gap(2));
+        assert_eq!(t.push(15), Sequence::Ok);
+        assert_eq!(t.push(3), Sequence::Reset);
+        assert_eq!(t.push(4), Sequence::Ok);
+
+        assert_eq!(t.dropped, 2);
+        assert_eq!(t.duplicates, 1);
+    }
+
+    #[test]
+    fn test_gap_rate() {
+        let mut t = SequenceTracker::default();
+        assert_eq!(t.take_gap_rate(), None);
+
+        for seq in (0..100).filter(|s| s % 10 != 5) {
+            t.push(seq);
+        }
+        assert_eq!(t.take_gap_rate(), Some(0.1));
+
+        t.push(100);
+        assert_eq!(t.take_gap_rate(), Some(0.0));
+    }
+}
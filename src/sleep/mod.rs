@@ -0,0 +1,158 @@
+//! Sleep tracking built on top of the Sensor biometrics
+
+pub mod session;
+pub mod stage;
+
+use jiff::Timestamp;
+use rumqttc::AsyncClient;
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    common::packet::BedSide,
+    config::{SidesConfig, TemperatureUnit},
+    mqtt::{publish_guaranteed_wait, publish_high_freq},
+    sensor::{biometrics::BiometricsManager, packet::TemperatureData, presence::PresenceState},
+};
+use session::{SessionSummary, SessionTracker};
+use stage::{EPOCH_SECS, Epoch, HeuristicClassifier, SleepStage, StageClassifier};
+
+const TOPIC_LEFT_STAGE: &str = "opensleep/state/sleep/left/stage";
+const TOPIC_RIGHT_STAGE: &str = "opensleep/state/sleep/right/stage";
+const TOPIC_LEFT_SUMMARY: &str = "opensleep/state/sleep/left/summary";
+const TOPIC_RIGHT_SUMMARY: &str = "opensleep/state/sleep/right/summary";
+
+pub struct SleepManager {
+    client: AsyncClient,
+    solo: bool,
+    last_epoch: Instant,
+    left: SideSleep,
+    right: SideSleep,
+}
+
+struct SideSleep {
+    side: BedSide,
+    topic_stage: &'static str,
+    topic_summary: &'static str,
+    classifier: Box<dyn StageClassifier>,
+    stage: Option<SleepStage>,
+    session: SessionTracker,
+}
+
+impl SleepManager {
+    pub fn new(client: AsyncClient, sides_config: &SidesConfig) -> Self {
+        Self {
+            client,
+            solo: sides_config.is_solo(),
+            last_epoch: Instant::now(),
+            left: SideSleep::new(BedSide::Left, TOPIC_LEFT_STAGE, TOPIC_LEFT_SUMMARY),
+            right: SideSleep::new(BedSide::Right, TOPIC_RIGHT_STAGE, TOPIC_RIGHT_SUMMARY),
+        }
+    }
+
+    pub fn set_sides_config(&mut self, sides_config: &SidesConfig) {
+        if self.solo != sides_config.is_solo() {
+            log::info!("Sleep tracking switched to solo={}", sides_config.is_solo());
+            self.solo = sides_config.is_solo();
+            self.left.reset();
+            self.right.reset();
+        }
+    }
+
+    pub fn update_presence(&mut self, presence: &PresenceState) {
+        let now = Timestamp::now();
+        if self.solo {
+            self.left.session.update_presence(presence.any, now);
+        } else {
+            self.left.session.update_presence(presence.left, now);
+            self.right.session.update_presence(presence.right, now);
+        }
+    }
+
+    pub fn update_temperature(&mut self, data: &TemperatureData) {
+        let left = data.bed_side(&BedSide::Left) as f32;
+        let right = data.bed_side(&BedSide::Right) as f32;
+        if self.solo {
+            self.left
+                .session
+                .add_temperature((left + right) / 2.0, data.ambient);
+        } else {
+            self.left.session.add_temperature(left, data.ambient);
+            self.right.session.add_temperature(right, data.ambient);
+        }
+    }
+
+    /// classifies epochs every `EPOCH_SECS` and publishes finished sessions
+    pub async fn update(&mut self, biometrics: &mut BiometricsManager, unit: TemperatureUnit) {
+        let now = Timestamp::now();
+        if let Some(summary) = self.left.session.tick(now) {
+            self.left
+                .publish_summary(&mut self.client, &summary, unit)
+                .await;
+        }
+        if let Some(summary) = self.right.session.tick(now) {
+            self.right
+                .publish_summary(&mut self.client, &summary, unit)
+                .await;
+        }
+
+        let instant = Instant::now();
+        if instant.duration_since(self.last_epoch) < Duration::from_secs(EPOCH_SECS as u64) {
+            return;
+        }
+        self.last_epoch = instant;
+
+        let left = biometrics.take_epoch(&BedSide::Left);
+        let right = biometrics.take_epoch(&BedSide::Right);
+
+        // solo mode only publishes to `left/`
+        if self.solo {
+            self.left
+                .update(&mut self.client, &Epoch::merge(left, right), now);
+        } else {
+            self.left.update(&mut self.client, &left, now);
+            self.right.update(&mut self.client, &right, now);
+        }
+    }
+}
+
+impl SideSleep {
+    fn new(side: BedSide, topic_stage: &'static str, topic_summary: &'static str) -> Self {
+        Self {
+            side,
+            topic_stage,
+            topic_summary,
+            classifier: Box::new(HeuristicClassifier::default()),
+            stage: None,
+            session: SessionTracker::default(),
+        }
+    }
+
+    fn update(&mut self, client: &mut AsyncClient, epoch: &Epoch, now: Timestamp) {
+        let stage = self.classifier.classify(epoch);
+        if self.stage != Some(stage) {
+            log::info!("Sleep stage[{}]: {stage}", self.side);
+            self.stage = Some(stage);
+        }
+        self.session.add_epoch(epoch, stage, now);
+        publish_high_freq(client, self.topic_stage, stage.to_string());
+    }
+
+    async fn publish_summary(
+        &self,
+        client: &mut AsyncClient,
+        summary: &SessionSummary,
+        unit: TemperatureUnit,
+    ) {
+        log::info!("Sleep session[{}]: {summary:?}", self.side);
+        match summary.to_json(unit) {
+            Ok(json) => publish_guaranteed_wait(client, self.topic_summary, true, json).await,
+            Err(e) => log::error!("Failed to serialize sleep summary: {e}"),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.classifier = Box::new(HeuristicClassifier::default());
+        self.stage = None;
+        self.session = SessionTracker::default();
+    }
+}
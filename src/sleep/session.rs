@@ -0,0 +1,286 @@
+use jiff::{SignedDuration, Timestamp};
+use serde::Serialize;
+
+use super::stage::{EPOCH_SECS, Epoch, SleepStage};
+use crate::config::TemperatureUnit;
+
+/// presence must last this long to start a session
+const START_DURATION: SignedDuration = SignedDuration::from_mins(5);
+/// the bed must be empty this long to end a session
+const END_DURATION: SignedDuration = SignedDuration::from_mins(30);
+
+/// Published at the end of every sleep session
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionSummary {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    /// minutes
+    pub time_in_bed: f32,
+    /// minutes from the start of the session to the first sleep epoch
+    pub time_to_fall_asleep: Option<f32>,
+    /// bed exits during the session (excluding the final one)
+    pub exits: u32,
+    /// centidegrees celcius
+    pub avg_bed_temp: Option<u16>,
+    /// centidegrees celcius
+    pub avg_ambient_temp: Option<u16>,
+    /// BPM
+    pub avg_heart_rate: Option<f32>,
+    /// breaths per minute
+    pub avg_breathing_rate: Option<f32>,
+    /// minutes per stage
+    pub awake: f32,
+    pub light: f32,
+    pub deep: f32,
+    pub rem: f32,
+}
+
+impl SessionSummary {
+    /// temperatures as `temp_reading`s in `unit`
+    pub fn to_json(&self, unit: TemperatureUnit) -> serde_json::Result<String> {
+        let mut json = serde_json::to_value(self)?;
+        if unit != TemperatureUnit::Celsius {
+            for (key, temp) in [
+                ("avg_bed_temp", self.avg_bed_temp),
+                ("avg_ambient_temp", self.avg_ambient_temp),
+            ] {
+                // via f64 so it isn't serialized as ex. 87.80000305175781
+                let temp = temp.map(|t| (unit.convert_reading(t) as f64 * 10.0).round() / 10.0);
+                json[key] = serde_json::to_value(temp)?;
+            }
+        }
+        serde_json::to_string(&json)
+    }
+}
+
+/// Tracks one side's sleep session from the first sustained
+/// presence to the final bed exit
+#[derive(Debug, Default)]
+pub struct SessionTracker {
+    occupied: bool,
+    /// when the current stretch of presence started
+    occupied_since: Option<Timestamp>,
+    /// when the side was last vacated
+    vacated_at: Option<Timestamp>,
+    session: Option<Session>,
+}
+
+#[derive(Debug)]
+struct Session {
+    start: Timestamp,
+    time_in_bed: SignedDuration,
+    exits: u32,
+    sleep_onset: Option<Timestamp>,
+    stage_epochs: [u32; 4],
+    bed_temp: Average,
+    ambient_temp: Average,
+    heart_rate: Average,
+    breathing_rate: Average,
+}
+
+#[derive(Debug, Default)]
+struct Average {
+    sum: f64,
+    count: u32,
+}
+
+impl Average {
+    fn add(&mut self, value: f64) {
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn get(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+}
+
+impl SessionTracker {
+    pub fn update_presence(&mut self, occupied: bool, now: Timestamp) {
+        if occupied == self.occupied {
+            return;
+        }
+        self.occupied = occupied;
+
+        if occupied {
+            self.occupied_since = Some(now);
+            return;
+        }
+
+        self.vacated_at = Some(now);
+        let since = self.occupied_since.take();
+        if let (Some(session), Some(since)) = (&mut self.session, since) {
+            session.time_in_bed += now.duration_since(since);
+            session.exits += 1;
+        }
+    }
+
+    /// returns a summary when a session ends
+    pub fn tick(&mut self, now: Timestamp) -> Option<SessionSummary> {
+        match (&self.session, self.occupied) {
+            (None, true) => {
+                let since = self.occupied_since?;
+                if now.duration_since(since) >= START_DURATION {
+                    log::info!("Sleep session started at {since}");
+                    self.session = Some(Session::new(since));
+                }
+                None
+            }
+            (Some(_), false) => {
+                let vacated_at = self.vacated_at?;
+                if now.duration_since(vacated_at) < END_DURATION {
+                    return None;
+                }
+                let session = self.session.take()?;
+                log::info!("Sleep session ended at {vacated_at}");
+                Some(session.summarize(vacated_at))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn add_epoch(&mut self, epoch: &Epoch, stage: SleepStage, now: Timestamp) {
+        let Some(session) = &mut self.session else {
+            return;
+        };
+
+        let idx = match stage {
+            SleepStage::Out => return,
+            SleepStage::Awake => 0,
+            SleepStage::Light => 1,
+            SleepStage::Deep => 2,
+            SleepStage::Rem => 3,
+        };
+        session.stage_epochs[idx] += 1;
+
+        if idx > 0 && session.sleep_onset.is_none() {
+            // stage is for the epoch that just ended
+            session.sleep_onset = Some(now - SignedDuration::from_secs(EPOCH_SECS as i64));
+        }
+
+        if let Some(hr) = epoch.heart_rate {
+            session.heart_rate.add(hr as f64);
+        }
+        if let Some(br) = epoch.breathing_rate {
+            session.breathing_rate.add(br as f64);
+        }
+    }
+
+    /// centidegrees celcius
+    pub fn add_temperature(&mut self, bed: f32, ambient: u16) {
+        if let Some(session) = &mut self.session
+            && self.occupied
+        {
+            session.bed_temp.add(bed as f64);
+            session.ambient_temp.add(ambient as f64);
+        }
+    }
+}
+
+impl Session {
+    fn new(start: Timestamp) -> Self {
+        Self {
+            start,
+            time_in_bed: SignedDuration::ZERO,
+            exits: 0,
+            sleep_onset: None,
+            stage_epochs: [0; 4],
+            bed_temp: Average::default(),
+            ambient_temp: Average::default(),
+            heart_rate: Average::default(),
+            breathing_rate: Average::default(),
+        }
+    }
+
+    fn summarize(self, end: Timestamp) -> SessionSummary {
+        let epoch_mins = EPOCH_SECS as f32 / 60.0;
+        SessionSummary {
+            start: self.start,
+            end,
+            time_in_bed: self.time_in_bed.as_secs_f32() / 60.0,
+            time_to_fall_asleep: self
+                .sleep_onset
+                .map(|t| t.duration_since(self.start).as_secs_f32().max(0.0) / 60.0),
+            // final exit ended the session
+            exits: self.exits.saturating_sub(1),
+            avg_bed_temp: self.bed_temp.get().map(|t| t as u16),
+            avg_ambient_temp: self.ambient_temp.get().map(|t| t as u16),
+            avg_heart_rate: self.heart_rate.get().map(|v| v as f32),
+            avg_breathing_rate: self.breathing_rate.get().map(|v| v as f32),
+            awake: self.stage_epochs[0] as f32 * epoch_mins,
+            light: self.stage_epochs[1] as f32 * epoch_mins,
+            deep: self.stage_epochs[2] as f32 * epoch_mins,
+            rem: self.stage_epochs[3] as f32 * epoch_mins,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(mins: i64) -> Timestamp {
+        Timestamp::from_second(1_700_000_000).unwrap() + SignedDuration::from_mins(mins)
+    }
+
+    fn epoch(hr: f32) -> Epoch {
+        Epoch {
+            occupied: true,
+            heart_rate: Some(hr),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_short_presence_ignored() {
+        let mut t = SessionTracker::default();
+        t.update_presence(true, at(0));
+        assert_eq!(t.tick(at(2)), None);
+        t.update_presence(false, at(3));
+        assert_eq!(t.tick(at(60)), None);
+        assert!(t.session.is_none());
+    }
+
+    #[test]
+    fn test_session() {
+        let mut t = SessionTracker::default();
+        t.update_presence(true, at(0));
+        assert_eq!(t.tick(at(5)), None);
+        assert!(t.session.is_some());
+
+        t.add_temperature(3000.0, 2000);
+        t.add_epoch(&epoch(70.0), SleepStage::Awake, at(10));
+        t.add_epoch(&epoch(60.0), SleepStage::Light, at(20));
+        t.add_epoch(&epoch(50.0), SleepStage::Deep, at(30));
+        t.add_temperature(3200.0, 2200);
+
+        // bathroom break
+        t.update_presence(false, at(120));
+        assert_eq!(t.tick(at(125)), None);
+        t.update_presence(true, at(130));
+
+        // final exit
+        t.update_presence(false, at(480));
+        assert_eq!(t.tick(at(500)), None);
+        let summary = t.tick(at(510)).unwrap();
+
+        assert_eq!(summary.start, at(0));
+        assert_eq!(summary.end, at(480));
+        assert_eq!(summary.time_in_bed, 470.0);
+        assert_eq!(summary.time_to_fall_asleep, Some(19.5));
+        assert_eq!(summary.exits, 1);
+        assert_eq!(summary.avg_bed_temp, Some(3100));
+        assert_eq!(summary.avg_ambient_temp, Some(2100));
+        let json = summary.to_json(TemperatureUnit::Fahrenheit).unwrap();
+        assert!(json.contains(r#""avg_bed_temp":87.8"#), "{json}");
+        assert!(json.contains(r#""avg_ambient_temp":69.8"#), "{json}");
+        let json = summary.to_json(TemperatureUnit::Celsius).unwrap();
+        assert!(json.contains(r#""avg_bed_temp":3100,"#), "{json}");
+        assert_eq!(summary.avg_heart_rate, Some(60.0));
+        assert_eq!(summary.avg_breathing_rate, None);
+        assert_eq!(summary.awake, 0.5);
+        assert_eq!(summary.light, 0.5);
+        assert_eq!(summary.deep, 0.5);
+        assert!(t.session.is_none());
+    }
+}
@@ -0,0 +1,191 @@
+use strum_macros::Display;
+
+/// Length of a sleep staging epoch
+pub const EPOCH_SECS: u32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum SleepStage {
+    /// side is not occupied
+    Out,
+    Awake,
+    Light,
+    Deep,
+    Rem,
+}
+
+/// Summary of one side's biometrics over an epoch
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Epoch {
+    /// occupied, without leaving during the epoch
+    pub occupied: bool,
+    /// seconds spent moving
+    pub moving_secs: u32,
+    /// average BPM of confident estimates
+    pub heart_rate: Option<f32>,
+    /// average breaths per minute of good quality estimates
+    pub breathing_rate: Option<f32>,
+    /// every breathing estimate in the epoch had a good signal
+    pub breathing_regular: bool,
+}
+
+impl Epoch {
+    /// combines both sides into one for `Solo` mode, preferring the occupied side
+    pub fn merge(left: Epoch, right: Epoch) -> Epoch {
+        match (left.occupied, right.occupied) {
+            (true, false) => left,
+            (false, true) => right,
+            (false, false) => Epoch::default(),
+            // sleeper is in the middle, combine
+            (true, true) => Epoch {
+                occupied: true,
+                moving_secs: left.moving_secs.max(right.moving_secs),
+                heart_rate: left.heart_rate.or(right.heart_rate),
+                breathing_rate: left.breathing_rate.or(right.breathing_rate),
+                breathing_regular: left.breathing_regular || right.breathing_regular,
+            },
+        }
+    }
+}
+
+/// Classifies a side's epochs into sleep stages.
+/// Implementations may keep state across epochs and are reset when the side is vacated.
+pub trait StageClassifier {
+    fn classify(&mut self, epoch: &Epoch) -> SleepStage;
+}
+
+/// moving for this many seconds of an epoch means awake
+const AWAKE_MOVING_SECS: u32 = 10;
+/// quiet epochs needed to fall (back) asleep (5 minutes)
+const ONSET_EPOCHS: u32 = 10;
+/// no REM in the first hour of sleep
+const MIN_REM_EPOCHS: u32 = 120;
+/// heart rate relative to the sleep baseline
+const DEEP_HR_RATIO: f32 = 0.95;
+const REM_HR_RATIO: f32 = 1.03;
+
+/// Simple rules based on movement, heart rate relative to the
+/// night's baseline and breathing regularity
+#[derive(Debug, Default)]
+pub struct HeuristicClassifier {
+    /// consecutive quiet epochs
+    quiet_epochs: u32,
+    /// epochs since sleep onset
+    asleep_epochs: u32,
+    hr_sum: f32,
+    hr_count: u32,
+}
+
+impl HeuristicClassifier {
+    fn hr_baseline(&self) -> Option<f32> {
+        (self.hr_count > 0).then(|| self.hr_sum / self.hr_count as f32)
+    }
+}
+
+impl StageClassifier for HeuristicClassifier {
+    fn classify(&mut self, epoch: &Epoch) -> SleepStage {
+        if !epoch.occupied {
+            *self = Self::default();
+            return SleepStage::Out;
+        }
+
+        if epoch.moving_secs >= AWAKE_MOVING_SECS {
+            self.quiet_epochs = 0;
+            return SleepStage::Awake;
+        }
+
+        self.quiet_epochs += 1;
+        if self.quiet_epochs < ONSET_EPOCHS {
+            return SleepStage::Awake;
+        }
+        self.asleep_epochs += 1;
+
+        let Some(hr) = epoch.heart_rate else {
+            return SleepStage::Light;
+        };
+
+        let ratio = self.hr_baseline().map(|b| hr / b);
+        self.hr_sum += hr;
+        self.hr_count += 1;
+
+        match ratio {
+            Some(r) if r <= DEEP_HR_RATIO && epoch.breathing_regular => SleepStage::Deep,
+            Some(r)
+                if self.asleep_epochs > MIN_REM_EPOCHS
+                    && epoch.moving_secs == 0
+                    && (r >= REM_HR_RATIO || !epoch.breathing_regular) =>
+            {
+                SleepStage::Rem
+            }
+            _ => SleepStage::Light,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiet(hr: f32, regular: bool) -> Epoch {
+        Epoch {
+            occupied: true,
+            moving_secs: 0,
+            heart_rate: Some(hr),
+            breathing_rate: Some(14.0),
+            breathing_regular: regular,
+        }
+    }
+
+    #[test]
+    fn test_out_and_awake() {
+        let mut c = HeuristicClassifier::default();
+        assert_eq!(c.classify(&Epoch::default()), SleepStage::Out);
+
+        let moving = Epoch {
+            moving_secs: 20,
+            ..quiet(70.0, true)
+        };
+        assert_eq!(c.classify(&moving), SleepStage::Awake);
+
+        // still falling asleep
+        for _ in 0..(ONSET_EPOCHS - 1) {
+            assert_eq!(c.classify(&quiet(70.0, true)), SleepStage::Awake);
+        }
+        assert_eq!(c.classify(&quiet(70.0, true)), SleepStage::Light);
+    }
+
+    #[test]
+    fn test_deep_and_rem() {
+        let mut c = HeuristicClassifier::default();
+        for _ in 0..(ONSET_EPOCHS + MIN_REM_EPOCHS) {
+            c.classify(&quiet(60.0, true));
+        }
+
+        assert_eq!(c.classify(&quiet(55.0, true)), SleepStage::Deep);
+        // irregular breathing stops deep sleep
+        assert_eq!(c.classify(&quiet(55.0, false)), SleepStage::Rem);
+        assert_eq!(c.classify(&quiet(66.0, true)), SleepStage::Rem);
+        assert_eq!(c.classify(&quiet(60.0, true)), SleepStage::Light);
+
+        // leaving the bed starts over
+        assert_eq!(c.classify(&Epoch::default()), SleepStage::Out);
+        assert_eq!(c.classify(&quiet(60.0, true)), SleepStage::Awake);
+    }
+
+    #[test]
+    fn test_merge() {
+        let left = quiet(60.0, true);
+        assert_eq!(Epoch::merge(left.clone(), Epoch::default()), left);
+        assert_eq!(Epoch::merge(Epoch::default(), left.clone()), left);
+        assert!(!Epoch::merge(Epoch::default(), Epoch::default()).occupied);
+
+        let right = Epoch {
+            moving_secs: 5,
+            heart_rate: None,
+            ..quiet(0.0, false)
+        };
+        let merged = Epoch::merge(left, right);
+        assert_eq!(merged.moving_secs, 5);
+        assert_eq!(merged.heart_rate, Some(60.0));
+    }
+}